@@ -4,12 +4,15 @@ use rand::{thread_rng, Rng};
 use shared_arena::ArenaBox;
 use shipyard::Get;
 
+// payloads for the benches below, their fields are never read
+#[allow(dead_code)]
 #[derive(edict::Component, shipyard::Component, Debug, Default)]
 struct Pos {
     x: f32,
     y: f32,
 }
 
+#[allow(dead_code)]
 #[derive(edict::Component, shipyard::Component, Debug, Default)]
 struct Rect {
     x: f32,
@@ -18,6 +21,7 @@ struct Rect {
     h: f32,
 }
 
+#[allow(dead_code)]
 #[derive(edict::Component, shipyard::Component, Debug, Default)]
 struct Margins {
     left: f32,
@@ -26,11 +30,13 @@ struct Margins {
     bottom: f32,
 }
 
+#[allow(dead_code)]
 #[derive(edict::Component, shipyard::Component, Debug, Default)]
 struct Opacity {
     opacity: f32,
 }
 
+#[allow(dead_code)]
 #[derive(edict::Component, shipyard::Component, Debug, Default)]
 struct Visible {
     visible: bool,
@@ -159,33 +165,43 @@ fn insert(c: &mut Criterion) {
     });
 }
 
-// fn insert_batch(c: &mut Criterion) {
-//     let size = 10_000;
-//     let mut g = c.benchmark_group("Insret batch");
-//     g.bench_function("hecs", |b| {
-//         b.iter_batched_ref(
-//             hecs::World::new,
-//             |s| {
-//                 s.spawn_batch((0..size).map(|_| Composed::default()))
-//                     .for_each(|_| {});
-//             },
-//             BatchSize::SmallInput,
-//         )
-//     });
-//     g.bench_function("nitro", |b| {
-//         b.iter_batched_ref(
-//             nitro::Storage::new,
-//             |s| {
-//                 let mut placer = s.placer::<Composed>();
-//                 for _ in 0..size {
-//                     placer.place(Composed::default());
-//                 }
-//             },
-//             BatchSize::SmallInput,
-//         )
-//     });
-//     todo!()
-// }
+fn insert_batch(c: &mut Criterion) {
+    let size = 10_000;
+    let mut g = c.benchmark_group("Insert batch");
+    g.bench_function("hecs", |b| {
+        b.iter_batched_ref(
+            hecs::World::new,
+            |s| {
+                s.spawn_batch((0..size).map(|_| Composed::default()))
+                    .for_each(|_| {});
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    g.bench_function("nitro place", |b| {
+        b.iter_batched_ref(
+            nitro::Storage::new,
+            |s| {
+                for _ in 0..size {
+                    s.place(Composed::default());
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    g.bench_function("nitro placer", |b| {
+        b.iter_batched_ref(
+            nitro::Storage::new,
+            |s| {
+                let mut placer = s.placer::<Composed>();
+                for _ in 0..size {
+                    placer.place(Composed::default());
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
 
 fn remove(c: &mut Criterion) {
     let size = 10_000;
@@ -489,5 +505,5 @@ fn get(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, insert, remove, get);
+criterion_group!(benches, insert, insert_batch, remove, get);
 criterion_main!(benches);
@@ -0,0 +1,145 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use nitro::Storage;
+
+fn hot_type(c: &mut Criterion) {
+    let size = 10_000;
+
+    let mut g = c.benchmark_group("Hot type");
+    for pinned in [false, true] {
+        let name = if pinned { "pinned" } else { "unpinned" };
+
+        g.bench_function(format!("place {name}"), |b| {
+            b.iter_with_large_drop(|| {
+                let mut storage = Storage::new();
+                storage.place(0u8);
+                storage.place(0u16);
+                if pinned {
+                    storage.pin_hot_type::<u64>();
+                }
+                for value in 0..size {
+                    storage.place::<u64>(value);
+                }
+                storage
+            })
+        });
+
+        let mut storage = Storage::new();
+        if pinned {
+            storage.pin_hot_type::<u64>();
+        }
+        let ids = (0..size)
+            .map(|value| storage.place::<u64>(value))
+            .collect::<Vec<_>>();
+        g.bench_function(format!("count_where {name}"), |b| {
+            b.iter(|| {
+                for _ in 0..size {
+                    black_box(storage.count_where::<u64, _>(|_| false));
+                }
+            })
+        });
+        g.bench_function(format!("get {name}"), |b| {
+            b.iter(|| {
+                for id in ids.iter() {
+                    black_box(storage.get::<u64>(id));
+                }
+            })
+        });
+    }
+}
+
+fn contains(c: &mut Criterion) {
+    let size = 10_000;
+
+    let mut storage = Storage::new();
+    let ids = (0..size)
+        .map(|value| storage.place::<u64>(value))
+        .collect::<Vec<_>>();
+    for id in ids.iter().step_by(3) {
+        storage.remove::<u64>(id);
+    }
+
+    let mut g = c.benchmark_group("Contains");
+    g.bench_function("loop", |b| {
+        let mut out = vec![false; ids.len()];
+        b.iter(|| {
+            for (id, contains) in ids.iter().zip(out.iter_mut()) {
+                *contains = storage.contains(id);
+            }
+            black_box(&out);
+        })
+    });
+    g.bench_function("batch", |b| {
+        let mut out = vec![false; ids.len()];
+        b.iter(|| {
+            storage.contains_batch(&ids, &mut out);
+            black_box(&out);
+        })
+    });
+}
+
+fn clear(c: &mut Criterion) {
+    let size = 1_000_000;
+
+    let mut g = c.benchmark_group("Clear");
+    g.bench_function("u32", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut storage = Storage::new();
+                storage.place_n_with(size, |value| value as u32);
+                storage
+            },
+            |storage| storage.clear(),
+            BatchSize::LargeInput,
+        )
+    });
+    g.bench_function("Box<u32>", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut storage = Storage::new();
+                storage.place_n_with(size, |value| Box::new(value as u32));
+                storage
+            },
+            |storage| storage.clear(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn erase(c: &mut Criterion) {
+    let size = 10_000;
+
+    let mut g = c.benchmark_group("Erase");
+    g.bench_function("u64", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut storage = Storage::new();
+                let ids = storage.place_n_with(size, |value| value as u64);
+                (storage, ids)
+            },
+            |(storage, ids)| {
+                for id in ids.iter() {
+                    storage.erase(id);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    g.bench_function("Box<u64>", |b| {
+        b.iter_batched_ref(
+            || {
+                let mut storage = Storage::new();
+                let ids = storage.place_n_with(size, |value| Box::new(value as u64));
+                (storage, ids)
+            },
+            |(storage, ids)| {
+                for id in ids.iter() {
+                    storage.erase(id);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, hot_type, contains, clear, erase);
+criterion_main!(benches);
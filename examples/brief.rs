@@ -5,7 +5,7 @@ fn main() {
     let ids = [
         storage.place::<u32>(0),
         storage.place::<u8>(1),
-        storage.place::<String>("2".into()),
+        storage.place_into::<String, _>("2"),
     ];
 
     assert_eq!(0, *storage.get::<u32>(&ids[0]));
@@ -18,7 +18,8 @@ fn main() {
     let value = storage.remove::<u32>(&ids[0]).unwrap();
     assert_eq!(value, 0);
 
-    for id in ids.iter() {
+    // ids[0] is removed already, erasing it again is a double removal
+    for id in ids[1..].iter() {
         storage.erase(id);
     }
 }
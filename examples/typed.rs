@@ -0,0 +1,27 @@
+use nitro::*;
+
+fn main() {
+    let mut storage = Storage::new();
+
+    // typed ids carry the type, no turbofish and no way to ask for the wrong type
+    let health = storage.place_typed::<u32>(100);
+    let name = storage.place_typed("player".to_string());
+
+    *storage.get_typed_mut(&health) -= 10;
+    assert_eq!(90, *storage.get_typed(&health));
+    assert_eq!("player", storage.get_typed(&name));
+
+    // erased ids fit in one collection, the type is given at the access
+    let ids = [health.into(), name.erase(), storage.place(0.5f32)];
+    assert_eq!(90, *storage.get::<u32>(&ids[0]));
+    assert_eq!(0.5, *storage.get::<f32>(&ids[2]));
+
+    assert_eq!(Some(90), storage.remove_typed(&health));
+    assert!(!storage.contains_typed(&health));
+    assert!(!storage.contains(&ids[0]));
+
+    // ids[0] is removed already, erasing it again is a double removal
+    for id in ids[1..].iter() {
+        storage.erase(id);
+    }
+}
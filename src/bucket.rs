@@ -1,7 +1,15 @@
-use crate::params::Size;
-use core::{alloc::Layout, marker::PhantomData, ptr::copy_nonoverlapping};
-use std::alloc::{alloc, dealloc};
-
+use crate::{error::TryReserveError, params::Size};
+use core::{
+    alloc::Layout,
+    any::Any,
+    marker::PhantomData,
+    ptr::{copy_nonoverlapping, swap_nonoverlapping},
+};
+use std::alloc::{alloc, dealloc, handle_alloc_error};
+
+// repr(C), so the payload is at offset 0 and the token index offset follows from the
+// layouts alone, which is what lets a bucket change its Size without knowing T
+#[repr(C)]
 struct Cell<T, S: Size> {
     data: T,
     token_index: S,
@@ -13,62 +21,187 @@ impl<T, S: Size> Cell<T, S> {
     }
 }
 
+// payload plus token_index, padded to the alignment of both
+pub(crate) const fn cell_layout<T, S: Size>() -> Layout {
+    Layout::new::<Cell<T, S>>()
+}
+
+pub(crate) fn cell_array_layout<T, S: Size>(len: usize) -> Layout {
+    Layout::array::<Cell<T, S>>(len).unwrap()
+}
+
+// layout of Cell<T, S> and the offset of its token index, from the layout of T
+fn erased_cell_layout<S: Size>(value_layout: Layout) -> (Layout, usize) {
+    let (layout, token_offset) = value_layout.extend(Layout::new::<S>()).unwrap();
+    (layout.pad_to_align(), token_offset)
+}
+
+pub(crate) const DEFAULT_START_CAPACITY: usize = 4;
+pub(crate) const DEFAULT_GROWTH_FACTOR: f64 = 2.0;
+
 pub(crate) struct Bucket<S: Size> {
     data: *mut u8,
     layout: Layout,
+    value_layout: Layout,
+    token_offset: usize,
+    type_name: &'static str,
     capacity: usize,
+    start_capacity: usize,
+    growth_factor: f64,
     len: usize,
+    high_water: usize,
+    version: u64,
+    // false for plain data, erase and clear then skip the drop thunk
+    needs_drop: bool,
+    // the thunks take a pointer to a cell and only touch the payload at its start,
+    // so they do not depend on S
     drop_fn: unsafe fn(*mut u8),
-    swap_fn: unsafe fn(*mut u8, *mut u8),
-    get_token_index_fn: unsafe fn(*mut u8) -> S,
-    get_array_layout: fn(len: usize) -> Layout,
+    into_any_fn: unsafe fn(*mut u8) -> Box<dyn Any>,
+    as_any_fn: unsafe fn(*mut u8) -> *mut dyn Any,
+    #[cfg(debug_assertions)]
+    modifications: usize,
     phantom: PhantomData<S>,
 }
 
 impl<S: Size> Bucket<S> {
-    pub fn new<T>() -> Self {
-        Self::with_capacity::<T>(0)
+    pub fn new<T: 'static>(start_capacity: usize) -> Self {
+        debug_assert!(start_capacity != 0);
+        Self {
+            start_capacity,
+            ..Self::with_capacity::<T>(0)
+        }
     }
 
-    pub fn with_capacity<T>(capacity: usize) -> Self {
+    pub fn with_capacity<T: 'static>(capacity: usize) -> Self {
         let data = if capacity != 0 {
-            let array_layout = Layout::array::<Cell<T, S>>(capacity).unwrap();
-            unsafe { std::alloc::alloc(array_layout) }
+            let array_layout = cell_array_layout::<T, S>(capacity);
+            let pointer = unsafe { alloc(array_layout) };
+            if pointer.is_null() {
+                handle_alloc_error(array_layout)
+            }
+            pointer
         } else {
             std::ptr::null_mut()
         };
 
+        let (layout, token_offset) = erased_cell_layout::<S>(Layout::new::<T>());
+        debug_assert!(layout == cell_layout::<T, S>());
+        debug_assert!(token_offset == core::mem::offset_of!(Cell<T, S>, token_index));
+
         Self {
             data,
-            layout: Layout::new::<Cell<T, S>>(),
+            layout,
+            value_layout: Layout::new::<T>(),
+            token_offset,
+            type_name: core::any::type_name::<T>(),
             capacity,
+            start_capacity: DEFAULT_START_CAPACITY,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
             len: 0,
-            drop_fn: |pointer| unsafe {
-                pointer.cast::<Cell<T, S>>().read();
-            },
-            swap_fn: |l, r| unsafe { l.cast::<Cell<T, S>>().swap(r.cast::<Cell<T, S>>()) },
-            get_token_index_fn: |pointer| unsafe { (*pointer.cast::<Cell<T, S>>()).token_index },
-            get_array_layout: |len| Layout::array::<Cell<T, S>>(len).unwrap(),
+            high_water: 0,
+            version: 0,
+            needs_drop: core::mem::needs_drop::<T>(),
+            drop_fn: |pointer| unsafe { pointer.cast::<T>().drop_in_place() },
+            into_any_fn: |pointer| unsafe { Box::new(pointer.cast::<T>().read()) },
+            as_any_fn: |pointer| pointer.cast::<T>() as *mut dyn Any,
+            #[cfg(debug_assertions)]
+            modifications: 0,
             phantom: Default::default(),
         }
     }
 
+    // every stored value must be a valid T
+    pub unsafe fn retype<T: 'static>(&mut self) {
+        let retyped = Self::with_capacity::<T>(0);
+        debug_assert!(self.layout == retyped.layout);
+
+        self.value_layout = retyped.value_layout;
+        self.token_offset = retyped.token_offset;
+        self.type_name = retyped.type_name;
+        self.needs_drop = retyped.needs_drop;
+        self.drop_fn = retyped.drop_fn;
+        self.into_any_fn = retyped.into_any_fn;
+        self.as_any_fn = retyped.as_any_fn;
+    }
+
     pub fn capacity(&self) -> usize {
         self.capacity
     }
 
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        self.growth_factor = factor;
+    }
+
+    // bytes a full pass over the values touches, payloads and token indices
+    pub fn iteration_bytes(&self) -> usize {
+        self.len * self.layout.pad_to_align().size()
+    }
+
+    // share of the capacity that is not used, 0.0 for an empty bucket
+    pub fn fragmentation(&self) -> f64 {
+        match self.capacity {
+            0 => 0.0,
+            capacity => 1.0 - self.len as f64 / capacity as f64,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    // highest len the bucket ever had
+    pub fn high_water(&self) -> usize {
+        self.high_water
+    }
+
+    pub fn allocated_bytes(&self) -> usize {
+        match self.capacity {
+            0 => 0,
+            capacity => self.array_layout(capacity).size(),
+        }
+    }
+
+    pub unsafe fn token_index_unchecked(&self, index: usize) -> S {
+        debug_assert!(index < self.len);
+        unsafe { self.read_token_index(self.get_pointer_unchecked(index)) }
+    }
+
+    // the value without its type, derived from the raw buffer so pointers to
+    // different indexes do not alias
+    pub unsafe fn any_ptr_unchecked(&self, index: usize) -> *mut dyn Any {
+        debug_assert!(index < self.len);
+        unsafe { (self.as_any_fn)(self.get_pointer_unchecked(index)) }
+    }
+
+    // type erased set_token_index_unchecked
+    pub unsafe fn set_token_index_at_unchecked(&mut self, index: usize, token_index: S) {
+        debug_assert!(index < self.len);
+        unsafe {
+            self.get_pointer_unchecked(index)
+                .add(self.token_offset)
+                .cast::<S>()
+                .write(token_index)
+        }
+    }
+
     // set_token_index_unchecked must be called after push
     pub unsafe fn push_unchecked<T: 'static>(&mut self, data: T) -> Result<S, T> {
-        debug_assert!(self.layout == Layout::new::<Cell<T, S>>());
+        debug_assert!(self.layout == cell_layout::<T, S>());
 
-        if self.len == self.capacity && !self.try_grow::<Cell<T, S>>() {
+        if self.len == self.capacity && !self.try_grow() {
             return Err(data);
         }
 
+        self.changed();
         let index = self.len;
         let pointer = unsafe { self.data.cast::<Cell<T, S>>().add(index) };
         unsafe { pointer.write(Cell::new(data, 0.into())) };
         self.len += 1;
+        self.high_water = usize::max(self.high_water, self.len);
         Ok(index.into())
     }
 
@@ -85,6 +218,7 @@ impl<S: Size> Bucket<S> {
     pub unsafe fn swap_remove_unchecked<T: 'static>(&mut self, index: S) -> (T, Option<S>) {
         let usize_index = index.into();
         debug_assert!(usize_index < self.len);
+        self.changed();
 
         let pointer_to_last = unsafe { self.data.cast::<Cell<T, S>>().add(self.len - 1) };
         if usize_index == self.len - 1 {
@@ -109,23 +243,30 @@ impl<S: Size> Bucket<S> {
     pub unsafe fn swap_erase_unchecked(&mut self, index: S) -> Option<S> {
         let usize_index = index.into();
         debug_assert!(usize_index < self.len);
+        self.changed();
 
         let pointer_to_last = self.get_pointer_unchecked(self.len - 1);
         if usize_index == self.len - 1 {
             self.len -= 1;
-            (self.drop_fn)(pointer_to_last);
+            if self.needs_drop {
+                (self.drop_fn)(pointer_to_last);
+            }
             return None;
         }
 
         let pointer = self.get_pointer_unchecked(usize_index);
-        (self.swap_fn)(pointer, pointer_to_last);
-        (self.drop_fn)(pointer_to_last);
+        if self.needs_drop {
+            swap_nonoverlapping(pointer, pointer_to_last, self.layout.size());
+            (self.drop_fn)(pointer_to_last);
+        } else {
+            unsafe { copy_nonoverlapping(pointer_to_last, pointer, self.layout.size()) }
+        }
         self.len -= 1;
-        unsafe { Some((self.get_token_index_fn)(pointer)) }
+        unsafe { Some(self.read_token_index(pointer)) }
     }
 
     pub fn try_get<T>(&self, index: S) -> Option<&T> {
-        if self.layout != Layout::new::<Cell<T, S>>() {
+        if self.layout != cell_layout::<T, S>() {
             return None;
         }
 
@@ -137,7 +278,7 @@ impl<S: Size> Bucket<S> {
     }
 
     pub unsafe fn get_unchecked<T>(&self, index: S) -> &T {
-        debug_assert!(self.layout == Layout::new::<Cell<T, S>>());
+        debug_assert!(self.layout == cell_layout::<T, S>());
         debug_assert!(index.into() < self.len);
 
         unsafe {
@@ -147,7 +288,7 @@ impl<S: Size> Bucket<S> {
     }
 
     pub fn try_get_mut<T>(&mut self, index: S) -> Option<&mut T> {
-        if self.layout != Layout::new::<Cell<T, S>>() {
+        if self.layout != cell_layout::<T, S>() {
             return None;
         }
 
@@ -159,7 +300,7 @@ impl<S: Size> Bucket<S> {
     }
 
     pub unsafe fn get_mut_unchecked<T>(&mut self, index: S) -> &mut T {
-        debug_assert!(self.layout == Layout::new::<Cell<T, S>>());
+        debug_assert!(self.layout == cell_layout::<T, S>());
         debug_assert!(index.into() < self.len);
 
         unsafe {
@@ -168,35 +309,170 @@ impl<S: Size> Bucket<S> {
         }
     }
 
-    fn try_grow<T>(&mut self) -> bool {
+    pub unsafe fn iter_unchecked<T: 'static>(&self) -> impl Iterator<Item = &T> {
+        self.guard(unsafe { self.cells_unchecked::<T>() }.iter())
+            .map(|cell| &cell.data)
+    }
+
+    // not guarded, the bucket can not change while it is borrowed mutably
+    pub unsafe fn iter_mut_with_token_indices_unchecked<T: 'static>(
+        &mut self,
+    ) -> impl Iterator<Item = (S, &mut T)> {
+        unsafe { self.cells_mut_unchecked::<T>() }
+            .iter_mut()
+            .map(|cell| (cell.token_index, &mut cell.data))
+    }
+
+    pub unsafe fn iter_with_token_indices_unchecked<T: 'static>(
+        &self,
+        from: usize,
+    ) -> impl Iterator<Item = (S, &T)> {
+        let cells = unsafe { self.cells_unchecked::<T>() };
+        self.guard(cells[usize::min(from, cells.len())..].iter())
+            .map(|cell| (cell.token_index, &cell.data))
+    }
+
+    fn guard<I: Iterator>(&self, iter: I) -> Guarded<'_, S, I> {
+        Guarded {
+            #[cfg(debug_assertions)]
+            modifications: self.modifications,
+            bucket: self,
+            iter,
+        }
+    }
+
+    // bumped on every place/remove/clear
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: u64) {
+        self.version = version;
+    }
+
+    fn changed(&mut self) {
+        self.version += 1;
+        self.modified();
+    }
+
+    // place/remove/clear and reallocations invalidate running iterators
+    fn modified(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            self.modifications = self.modifications.wrapping_add(1);
+        }
+    }
+
+    unsafe fn cells_unchecked<T>(&self) -> &[Cell<T, S>] {
+        debug_assert!(self.layout == cell_layout::<T, S>());
+
+        if self.len != 0 {
+            unsafe { core::slice::from_raw_parts(self.data.cast(), self.len) }
+        } else {
+            &[]
+        }
+    }
+
+    unsafe fn cells_mut_unchecked<T>(&mut self) -> &mut [Cell<T, S>] {
+        debug_assert!(self.layout == cell_layout::<T, S>());
+
+        if self.len != 0 {
+            unsafe { core::slice::from_raw_parts_mut(self.data.cast(), self.len) }
+        } else {
+            &mut []
+        }
+    }
+
+    fn try_grow(&mut self) -> bool {
         if self.capacity == S::max() {
             return false;
         }
 
         let new_capacity = if self.capacity != 0 {
-            usize::min(self.capacity << 1, S::max())
+            let grown = (self.capacity as f64 * self.growth_factor) as usize;
+            usize::min(usize::max(grown, self.capacity + 1), S::max())
         } else {
-            4 //#TODO setup start capacity
+            usize::min(self.start_capacity, S::max())
         };
 
-        let layout = Layout::array::<Cell<T, S>>(new_capacity).unwrap();
+        self.grow_to(new_capacity);
+        true
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        match self.try_reserve(additional) {
+            Ok(()) => {}
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => handle_alloc_error(layout),
+        }
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        match self.try_reserve_exact(additional) {
+            Ok(()) => {}
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => handle_alloc_error(layout),
+        }
+    }
+
+    // grows by at least the growth factor, so repeated reserves stay amortized
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self.required_capacity(additional)?;
+        if required > self.capacity {
+            let grown = (self.capacity as f64 * self.growth_factor) as usize;
+            self.try_grow_to(usize::max(required, usize::min(grown, S::max())))?;
+        }
+        Ok(())
+    }
+
+    pub fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let required = self.required_capacity(additional)?;
+        if required > self.capacity {
+            self.try_grow_to(required)?;
+        }
+        Ok(())
+    }
+
+    fn required_capacity(&self, additional: usize) -> Result<usize, TryReserveError> {
+        match self.len.checked_add(additional) {
+            Some(required) if required <= S::max() => Ok(required),
+            _ => Err(TryReserveError::CapacityOverflow),
+        }
+    }
+
+    fn grow_to(&mut self, new_capacity: usize) {
+        match self.try_grow_to(new_capacity) {
+            Ok(()) => {}
+            Err(TryReserveError::CapacityOverflow) => panic!("capacity overflow"),
+            Err(TryReserveError::AllocError { layout }) => handle_alloc_error(layout),
+        }
+    }
+
+    fn try_grow_to(&mut self, new_capacity: usize) -> Result<(), TryReserveError> {
+        debug_assert!(new_capacity > self.capacity);
+
+        let stride = self.layout.pad_to_align().size();
+        if new_capacity > isize::MAX as usize / stride {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+
+        let layout = self.array_layout(new_capacity);
         let pointer = unsafe { alloc(layout) };
+        if pointer.is_null() {
+            return Err(TryReserveError::AllocError { layout });
+        }
 
+        self.modified();
         if !self.data.is_null() {
-            unsafe {
-                copy_nonoverlapping(
-                    self.data.cast::<Cell<T, S>>(),
-                    pointer.cast::<Cell<T, S>>(),
-                    self.len,
-                );
-            }
+            unsafe { copy_nonoverlapping(self.data, pointer, self.layout.size() * self.len) }
 
-            unsafe { dealloc(self.data, layout) }
+            let old_layout = self.array_layout(self.capacity);
+            unsafe { dealloc(self.data, old_layout) }
         }
 
         self.data = pointer;
         self.capacity = new_capacity;
-        true
+        Ok(())
     }
 
     pub unsafe fn shrink_to_fit(&mut self) {
@@ -204,15 +480,20 @@ impl<S: Size> Bucket<S> {
             return;
         }
 
+        self.modified();
         let mut pointer = std::ptr::null_mut();
         if self.len != 0 {
-            let layout = (self.get_array_layout)(self.len);
+            let layout = self.array_layout(self.len);
             pointer = unsafe { alloc(layout) };
-            unsafe { copy_nonoverlapping(self.data, pointer, layout.size() * self.len) }
+            if pointer.is_null() {
+                // keeping the larger allocation is still valid
+                return;
+            }
+            unsafe { copy_nonoverlapping(self.data, pointer, layout.size()) }
         }
 
         if !self.data.is_null() {
-            let layout = (self.get_array_layout)(self.capacity);
+            let layout = self.array_layout(self.capacity);
             unsafe { dealloc(self.data, layout) }
         }
 
@@ -220,43 +501,264 @@ impl<S: Size> Bucket<S> {
         self.capacity = self.len;
     }
 
+    // moves every value out in bucket order, leaves the bucket empty
+    pub fn drain_any(&mut self, mut f: impl FnMut(S, Box<dyn Any>)) {
+        self.changed();
+        let len = self.len;
+        self.len = 0;
+
+        for index in 0..len {
+            unsafe {
+                let pointer = self.get_pointer_unchecked(index);
+                f(self.read_token_index(pointer), (self.into_any_fn)(pointer))
+            }
+        }
+    }
+
+    // T must be the type of the bucket, same order and end state as drain_any
+    pub unsafe fn drain_unchecked<T: 'static>(&mut self, mut f: impl FnMut(S, T)) {
+        debug_assert!(self.layout == cell_layout::<T, S>());
+
+        self.changed();
+        let len = self.len;
+        self.len = 0;
+
+        for index in 0..len {
+            let cell = unsafe {
+                self.get_pointer_unchecked(index)
+                    .cast::<Cell<T, S>>()
+                    .read()
+            };
+            f(cell.token_index, cell.data)
+        }
+    }
+
+    // moves the values into a bucket for NewS in bucket order and leaves this one empty,
+    // the callback gets the old token index of every value and returns the new one
+    pub fn migrate<NewS: Size>(
+        &mut self,
+        new_token_index: &mut dyn FnMut(S) -> usize,
+    ) -> Bucket<NewS> {
+        let (layout, token_offset) = erased_cell_layout::<NewS>(self.value_layout);
+        let mut bucket = Bucket::<NewS> {
+            data: std::ptr::null_mut(),
+            layout,
+            value_layout: self.value_layout,
+            token_offset,
+            type_name: self.type_name,
+            capacity: 0,
+            start_capacity: self.start_capacity,
+            growth_factor: self.growth_factor,
+            len: 0,
+            high_water: 0,
+            version: self.version + 1,
+            needs_drop: self.needs_drop,
+            drop_fn: self.drop_fn,
+            into_any_fn: self.into_any_fn,
+            as_any_fn: self.as_any_fn,
+            #[cfg(debug_assertions)]
+            modifications: 0,
+            phantom: PhantomData,
+        };
+        if self.len != 0 {
+            bucket.grow_to(self.len);
+        }
+
+        self.changed();
+        let len = self.len;
+        self.len = 0;
+        for index in 0..len {
+            unsafe {
+                let from = self.get_pointer_unchecked(index);
+                let token_index = NewS::from(new_token_index(self.read_token_index(from)));
+                let to = bucket.get_pointer_unchecked(index);
+                copy_nonoverlapping(from, to, self.value_layout.size());
+                to.add(token_offset).cast::<NewS>().write(token_index);
+            }
+        }
+        bucket.len = len;
+        bucket.high_water = len;
+        bucket
+    }
+
+    unsafe fn read_token_index(&self, pointer: *mut u8) -> S {
+        unsafe { pointer.add(self.token_offset).cast::<S>().read() }
+    }
+
+    fn array_layout(&self, len: usize) -> Layout {
+        Layout::from_size_align(self.layout.size() * len, self.layout.align()).unwrap()
+    }
+
     pub unsafe fn clear(&mut self) {
         if self.len == 0 {
             return;
         }
 
-        let mut index = self.len;
-        loop {
-            if index == 0 {
-                break;
+        self.changed();
+        if !self.needs_drop {
+            self.len = 0;
+            return;
+        }
+
+        // len is zeroed first, so nothing is dropped twice if a drop panics
+        let mut rest = DropRest {
+            index: self.len,
+            bucket: self,
+        };
+        rest.bucket.len = 0;
+        rest.drop_rest();
+    }
+
+    pub unsafe fn drop(bucket: &mut Self) {
+        // the allocation is freed even if a drop panics
+        struct Dealloc<'a, S: Size>(&'a mut Bucket<S>);
+
+        impl<'a, S: Size> Drop for Dealloc<'a, S> {
+            fn drop(&mut self) {
+                if self.0.capacity == 0 {
+                    return;
+                }
+
+                let array_layout = self.0.array_layout(self.0.capacity);
+                unsafe { dealloc(self.0.data, array_layout) }
             }
+        }
+
+        let dealloc = Dealloc(bucket);
+        unsafe { Self::clear(dealloc.0) }
+    }
 
-            index -= 1;
+    unsafe fn get_pointer_unchecked(&self, index: usize) -> *mut u8 {
+        let aligned = self.layout.pad_to_align();
+        unsafe { self.data.add(aligned.size() * index) }
+    }
+}
+
+// drops values from index down to 0, on unwind it keeps going with the rest
+// like Vec does, a second panic aborts
+struct DropRest<'a, S: Size> {
+    bucket: &'a mut Bucket<S>,
+    index: usize,
+}
+
+impl<'a, S: Size> DropRest<'a, S> {
+    fn drop_rest(&mut self) {
+        while self.index != 0 {
+            self.index -= 1;
 
             unsafe {
-                let pointer = self.get_pointer_unchecked(index);
-                (self.drop_fn)(pointer)
+                let pointer = self.bucket.get_pointer_unchecked(self.index);
+                (self.bucket.drop_fn)(pointer)
             }
         }
     }
+}
 
-    pub unsafe fn drop(bucket: &mut Self) {
-        Self::clear(bucket);
+impl<'a, S: Size> Drop for DropRest<'a, S> {
+    fn drop(&mut self) {
+        self.drop_rest();
+    }
+}
 
-        if Self::capacity(bucket) == 0 {
-            return;
+// panics in debug builds if the bucket was modified while iterating
+struct Guarded<'a, S: Size, I> {
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    bucket: &'a Bucket<S>,
+    #[cfg(debug_assertions)]
+    modifications: usize,
+    iter: I,
+}
+
+impl<'a, S: Size, I: Iterator> Iterator for Guarded<'a, S, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        // volatile, the bucket can only change through aliasing the shared reference,
+        // which is undefined behaviour already, so this is a debug aid and sound code
+        // never trips it
+        #[cfg(debug_assertions)]
+        if unsafe { core::ptr::read_volatile(&self.bucket.modifications) } != self.modifications {
+            panic!("bucket was modified during iteration");
         }
 
-        let array_layout = core::alloc::Layout::from_size_align_unchecked(
-            bucket.layout.size() * bucket.capacity,
-            bucket.layout.align(),
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+mod tests {
+    #[test]
+    fn array_layout_matches_allocation() {
+        use super::*;
+        use crate::U32Size;
+
+        type Padded = (u64, u8);
+
+        let mut bucket = Bucket::<U32Size>::new::<Padded>(DEFAULT_START_CAPACITY);
+        for value in 0..100u8 {
+            unsafe { bucket.push_unchecked::<Padded>((value as u64, value)).ok() };
+        }
+        assert_eq!(
+            bucket.array_layout(bucket.capacity()),
+            cell_array_layout::<Padded, U32Size>(bucket.capacity())
         );
 
-        unsafe { dealloc(bucket.data, array_layout) }
+        for _ in 0..90 {
+            unsafe { bucket.swap_remove_unchecked::<Padded>(0.into()) };
+        }
+        unsafe { bucket.shrink_to_fit() };
+        assert_eq!(bucket.capacity(), 10);
+
+        let mut values = (0..10)
+            .map(|index| unsafe { bucket.get_unchecked::<Padded>(index.into()).1 })
+            .collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, (1..11).collect::<Vec<_>>());
+
+        unsafe { Bucket::drop(&mut bucket) };
     }
 
-    unsafe fn get_pointer_unchecked(&self, index: usize) -> *mut u8 {
-        let aligned = self.layout.pad_to_align();
-        unsafe { self.data.add(aligned.size() * index) }
+    #[test]
+    fn cell_layout() {
+        use super::*;
+        use crate::{U32Size, USize};
+
+        assert_eq!(cell_layout::<(), U32Size>(), Layout::new::<u32>());
+        assert_eq!(
+            cell_layout::<u8, U32Size>(),
+            Layout::from_size_align(8, 4).unwrap()
+        );
+        assert_eq!(
+            cell_layout::<u64, U32Size>(),
+            Layout::from_size_align(16, 8).unwrap()
+        );
+        assert_eq!(
+            cell_layout::<[u8; 3], USize>().size(),
+            2 * size_of::<usize>()
+        );
+        assert_eq!(
+            cell_array_layout::<u8, U32Size>(10).size(),
+            10 * cell_layout::<u8, U32Size>().size()
+        );
+    }
+
+    // a stale snapshot stands in for a modification, nothing can modify the bucket
+    // soundly while it is borrowed
+    #[cfg(debug_assertions)]
+    #[test]
+    fn guard_notices_modifications() {
+        use super::*;
+        use crate::U32Size;
+
+        let bucket = Bucket::<U32Size>::new::<u32>(DEFAULT_START_CAPACITY);
+        let mut iter = bucket.guard(0..3);
+        assert_eq!(iter.next(), Some(0));
+
+        iter.modifications = iter.modifications.wrapping_sub(1);
+        let next = std::panic::AssertUnwindSafe(|| iter.next());
+        assert!(std::panic::catch_unwind(next).is_err());
     }
 }
@@ -0,0 +1,104 @@
+use crate::{
+    id::Id,
+    params::{Size, U32Size, Unique32, UniqueTag},
+    storage::Storage,
+};
+
+type PlaceFn<S, U> = Box<dyn FnOnce(&mut Storage<S, U>) -> Id<S, U>>;
+
+enum Command<S: Size, U: UniqueTag> {
+    Place(PlaceFn<S, U>),
+    Remove(Id<S, U>, fn(&mut Storage<S, U>, &Id<S, U>)),
+    Erase(Id<S, U>),
+}
+
+// records structural changes to apply them later with Storage::apply
+pub struct CommandBuffer<S: Size = U32Size, U: UniqueTag = Unique32> {
+    commands: Vec<Command<S, U>>,
+}
+
+impl<S: Size, U: UniqueTag> CommandBuffer<S, U> {
+    pub fn new() -> Self {
+        Self { commands: vec![] }
+    }
+
+    pub fn place<T: 'static>(&mut self, data: T) {
+        self.commands
+            .push(Command::Place(Box::new(move |storage| storage.place(data))));
+    }
+
+    pub fn remove<T: 'static>(&mut self, id: Id<S, U>) {
+        self.commands.push(Command::Remove(id, |storage, id| {
+            storage.remove::<T>(id);
+        }));
+    }
+
+    pub fn erase(&mut self, id: Id<S, U>) {
+        self.commands.push(Command::Erase(id));
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // returns ids of placed values in the order they were queued
+    pub(crate) fn apply(self, storage: &mut Storage<S, U>) -> Vec<Id<S, U>> {
+        let mut placed = vec![];
+        for command in self.commands {
+            match command {
+                Command::Place(place) => placed.push(place(storage)),
+                Command::Remove(id, remove) => remove(storage, &id),
+                Command::Erase(id) => storage.erase(&id),
+            }
+        }
+        placed
+    }
+}
+
+impl<S: Size, U: UniqueTag> Default for CommandBuffer<S, U> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+mod tests {
+    #[test]
+    fn apply_after_iteration() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32)
+            .map(|value| storage.place(value))
+            .collect::<Vec<_>>();
+
+        let mut buffer = CommandBuffer::new();
+        for id in ids.iter() {
+            let value = storage.get_mut::<u32>(id);
+            *value *= 10;
+            if !value.is_multiple_of(20) {
+                buffer.remove::<u32>(*id);
+                buffer.place(*value as u64);
+            }
+        }
+        buffer.erase(ids[0]);
+        assert_eq!(buffer.len(), 11);
+
+        let placed = storage.apply(buffer);
+        assert_eq!(placed.len(), 5);
+
+        for (value, id) in ids.iter().enumerate() {
+            if value % 2 == 0 && value != 0 {
+                assert_eq!(*storage.get::<u32>(id), value as u32 * 10);
+            } else {
+                assert!(!storage.contains(id));
+            }
+        }
+        for (id, value) in placed.iter().zip([10, 30, 50, 70, 90]) {
+            assert_eq!(*storage.get::<u64>(id), value);
+        }
+    }
+}
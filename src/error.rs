@@ -0,0 +1,80 @@
+use core::{
+    alloc::Layout,
+    fmt::{self, Display},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TryReserveError {
+    // the capacity exceeds Size::max() or isize::MAX bytes
+    CapacityOverflow,
+    // the allocator returned null
+    AllocError { layout: Layout },
+}
+
+impl Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => write!(f, "capacity overflow"),
+            TryReserveError::AllocError { layout } => {
+                write!(f, "allocation of {} bytes failed", layout.size())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TryReserveError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlaceError<T> {
+    // the bucket of T already holds Size::max() values
+    BucketFull(T),
+    // every token index is taken, locked tokens are never reused
+    TokensExhausted(T),
+}
+
+impl<T> PlaceError<T> {
+    // the value that was not placed
+    pub fn into_inner(self) -> T {
+        match self {
+            PlaceError::BucketFull(data) | PlaceError::TokensExhausted(data) => data,
+        }
+    }
+}
+
+impl<T> Display for PlaceError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlaceError::BucketFull(_) => write!(f, "bucket is full"),
+            PlaceError::TokensExhausted(_) => write!(f, "no token index left"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for PlaceError<T> {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemoveError {
+    // the value was removed, or the id never belonged to this storage
+    Stale,
+    // the value was removed and its token ran out of generations
+    Locked,
+    // the id is live, but the value is not of the requested type
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl Display for RemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoveError::Stale => write!(f, "the value was already removed"),
+            RemoveError::Locked => write!(f, "the value was removed, its token is locked"),
+            RemoveError::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch (expected {expected}, found {found})")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RemoveError {}
@@ -0,0 +1,258 @@
+use crate::params::{Size, U32Size, Unique32, UniqueTag};
+use core::{
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Id<S: Size, U: UniqueTag> {
+    token_index: S,
+    tag: U,
+}
+
+impl<S: Size, U: UniqueTag> Id<S, U> {
+    pub(crate) fn new(token_index: S, tag: U) -> Self {
+        Self { tag, token_index }
+    }
+
+    // (token index, tag), e.g. for storing ids in foreign code
+    pub fn into_raw(self) -> (usize, u128) {
+        (self.token_index.into(), self.tag.to_raw())
+    }
+
+    // a made up pair is safe to use, it is just not contained by any storage.
+    // Panics if token_index does not fit S, see try_from_raw
+    pub fn from_raw(token_index: usize, tag: u128) -> Self {
+        Self::try_from_raw(token_index, tag).expect("token index does not fit the Size")
+    }
+
+    // None if token_index does not fit S
+    pub fn try_from_raw(token_index: usize, tag: u128) -> Option<Self> {
+        if !S::fits(token_index) {
+            return None;
+        }
+        Some(Self::new(token_index.into(), U::from_raw(tag)))
+    }
+
+    // drops the generation, see WeakId
+    pub fn weak(&self) -> WeakId<S> {
+        WeakId {
+            token_index: self.token_index,
+        }
+    }
+
+    // never refers to a value, token_index is the highest S can hold, capped at
+    // u32::MAX so that USize holds it too
+    pub fn dangling() -> Self {
+        Self::new(usize::min(S::max(), u32::MAX as usize).into(), U::default())
+    }
+
+    pub(crate) fn token_index(&self) -> S {
+        self.token_index
+    }
+
+    pub(crate) fn tag(&self) -> U {
+        self.tag
+    }
+
+    // little-endian token index followed by the tag, Size::bytes() + UniqueTag::bytes() long
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let token_index: usize = self.token_index.into();
+        buf.extend_from_slice(&token_index.to_le_bytes()[..S::bytes()]);
+        buf.extend_from_slice(&self.tag.to_raw().to_le_bytes()[..U::bytes()]);
+    }
+
+    // returns the id and the number of bytes read, None if buf is too short or the
+    // token index does not fit S
+    pub fn decode(buf: &[u8]) -> Option<(Self, usize)> {
+        let len = S::bytes() + U::bytes();
+        if buf.len() < len {
+            return None;
+        }
+
+        let mut token_index = [0; core::mem::size_of::<usize>()];
+        token_index[..S::bytes()].copy_from_slice(&buf[..S::bytes()]);
+        let mut tag = [0; core::mem::size_of::<u128>()];
+        tag[..U::bytes()].copy_from_slice(&buf[S::bytes()..len]);
+
+        let token_index = usize::from_le_bytes(token_index);
+        if !S::fits(token_index) {
+            return None;
+        }
+        let id = Self::new(token_index.into(), U::from_raw(u128::from_le_bytes(tag)));
+        Some((id, len))
+    }
+
+    // generation part of the id, widened from the backing tag
+    pub fn tag_raw(&self) -> u128 {
+        self.tag.current() as _
+    }
+}
+
+// identifies a slot, not a value: once the value is removed it silently
+// follows whatever is placed into the slot next, so it gives no ABA safety
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct WeakId<S: Size = U32Size> {
+    token_index: S,
+}
+
+impl<S: Size> WeakId<S> {
+    pub(crate) fn token_index(&self) -> S {
+        self.token_index
+    }
+}
+
+// id that statically knows the type of the value it refers to
+pub struct Tid<T, S: Size = U32Size, U: UniqueTag = Unique32> {
+    id: Id<S, U>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T, S: Size, U: UniqueTag> Tid<T, S, U> {
+    pub(crate) fn new(id: Id<S, U>) -> Self {
+        Self {
+            id,
+            phantom: PhantomData,
+        }
+    }
+
+    pub(crate) fn id(&self) -> &Id<S, U> {
+        &self.id
+    }
+
+    // drops the static type, for collections of ids of different types
+    pub fn erase(self) -> Id<S, U> {
+        self.id
+    }
+}
+
+impl<T, S: Size, U: UniqueTag> From<Tid<T, S, U>> for Id<S, U> {
+    fn from(id: Tid<T, S, U>) -> Self {
+        id.erase()
+    }
+}
+
+impl<T, S: Size, U: UniqueTag> Copy for Tid<T, S, U> {}
+
+impl<T, S: Size, U: UniqueTag> Clone for Tid<T, S, U> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, S: Size, U: UniqueTag> PartialEq for Tid<T, S, U> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T, S: Size, U: UniqueTag> Eq for Tid<T, S, U> {}
+
+impl<T, S: Size, U: UniqueTag> Hash for Tid<T, S, U> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl<T, S: Size, U: UniqueTag> Debug for Tid<T, S, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tid")
+            .field("type", &core::any::type_name::<T>())
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+mod tests {
+    #[test]
+    fn encode_decode() {
+        use super::*;
+        use crate::params::*;
+
+        fn round_trip<S: Size, U: UniqueTag>() {
+            let mut tag = U::default();
+            for _ in 0..3 {
+                tag = tag.next();
+            }
+            let ids = [
+                Id::<S, U>::new(0.into(), U::default()),
+                Id::new((u32::MAX as usize).into(), tag),
+                Id::new(12345.into(), tag),
+            ];
+
+            let mut buf = vec![];
+            for id in ids.iter() {
+                id.encode(&mut buf);
+            }
+            assert_eq!(buf.len(), ids.len() * (S::bytes() + U::bytes()));
+
+            let mut offset = 0;
+            for id in ids.iter() {
+                let (decoded, read) = Id::<S, U>::decode(&buf[offset..]).unwrap();
+                assert_eq!(decoded, *id);
+                offset += read;
+            }
+            assert_eq!(offset, buf.len());
+        }
+
+        fn round_trip_tags<S: Size>() {
+            round_trip::<S, Unique32>();
+            round_trip::<S, Unique64>();
+            round_trip::<S, Unique128>();
+            round_trip::<S, RepeatIn32>();
+            round_trip::<S, RepeatIn64>();
+            round_trip::<S, RepeatIn128>();
+        }
+
+        round_trip_tags::<U32Size>();
+        round_trip_tags::<USize>();
+    }
+
+    #[test]
+    fn decode_truncated() {
+        use super::*;
+
+        let mut buf = vec![];
+        Id::<U32Size, Unique32>::new(7.into(), Unique32::default()).encode(&mut buf);
+        assert_eq!(buf.len(), 8);
+
+        for len in 0..buf.len() {
+            assert!(Id::<U32Size, Unique32>::decode(&buf[..len]).is_none());
+        }
+        assert!(Id::<U32Size, Unique32>::decode(&buf).is_some());
+    }
+
+    #[test]
+    fn decode_out_of_range() {
+        use super::*;
+        use crate::params::USize;
+
+        let len = USize::bytes() + Unique32::bytes();
+        assert!(Id::<USize, Unique32>::decode(&vec![0xff; len]).is_none());
+
+        let mut buf = vec![];
+        Id::<USize, Unique32>::new((u32::MAX as usize).into(), Unique32::default())
+            .encode(&mut buf);
+        assert!(Id::<USize, Unique32>::decode(&buf).is_some());
+        buf[4] = 1;
+        assert!(Id::<USize, Unique32>::decode(&buf).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "token index does not fit the Size")]
+    fn from_raw_out_of_range() {
+        use super::*;
+
+        Id::<U32Size, Unique32>::from_raw(usize::MAX, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "token index does not fit the Size")]
+    fn from_raw_out_of_range_usize() {
+        use super::*;
+        use crate::params::USize;
+
+        Id::<USize, Unique32>::from_raw(u32::MAX as usize + 1, 0);
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    id::Id,
+    params::{Size, U32Size, Unique32, UniqueTag},
+    storage::Storage,
+};
+use core::hash::Hash;
+use std::collections::HashMap;
+
+// storage addressed by user keys, the key maps are kept in sync on every removal
+pub struct KeyedStorage<K: Hash + Eq + Clone, S: Size = U32Size, U: UniqueTag = Unique32> {
+    storage: Storage<S, U>,
+    ids: HashMap<K, Id<S, U>>,
+    keys: HashMap<Id<S, U>, K>,
+}
+
+impl<K: Hash + Eq + Clone> KeyedStorage<K> {
+    pub fn new() -> Self {
+        Self::with_storage(Storage::new())
+    }
+}
+
+impl<K: Hash + Eq + Clone, S: Size, U: UniqueTag> KeyedStorage<K, S, U> {
+    pub fn with_storage(storage: Storage<S, U>) -> Self {
+        Self {
+            storage,
+            ids: HashMap::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    // erases the value previously inserted under key
+    pub fn insert<T: 'static>(&mut self, key: K, data: T) -> Id<S, U> {
+        if let Some(old) = self.ids.remove(&key) {
+            self.keys.remove(&old);
+            self.storage.erase(&old);
+        }
+
+        let id = self.storage.place(data);
+        self.ids.insert(key.clone(), id);
+        self.keys.insert(id, key);
+        id
+    }
+
+    pub fn id_of(&self, key: &K) -> Option<Id<S, U>> {
+        self.ids.get(key).copied()
+    }
+
+    pub fn key_of(&self, id: &Id<S, U>) -> Option<&K> {
+        self.keys.get(id)
+    }
+
+    pub fn get_by_key<T: 'static>(&self, key: &K) -> Option<&T> {
+        self.storage.try_get(self.ids.get(key)?)
+    }
+
+    pub fn get_by_key_mut<T: 'static>(&mut self, key: &K) -> Option<&mut T> {
+        self.storage.try_get_mut(self.ids.get(key)?)
+    }
+
+    pub fn remove_by_key<T: 'static>(&mut self, key: &K) -> Option<T> {
+        let id = self.id_of(key)?;
+        self.remove(&id)
+    }
+
+    pub fn remove<T: 'static>(&mut self, id: &Id<S, U>) -> Option<T> {
+        let data = self.storage.remove(id)?;
+        self.forget(id);
+        Some(data)
+    }
+
+    pub fn erase(&mut self, id: &Id<S, U>) {
+        self.storage.erase(id);
+        self.forget(id);
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.ids.contains_key(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn storage(&self) -> &Storage<S, U> {
+        &self.storage
+    }
+
+    fn forget(&mut self, id: &Id<S, U>) {
+        if let Some(key) = self.keys.remove(id) {
+            self.ids.remove(&key);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for KeyedStorage<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+mod tests {
+    #[test]
+    fn insert_get_remove() {
+        use super::*;
+
+        let mut storage = KeyedStorage::new();
+        let player = storage.insert("player", String::from("hero"));
+        storage.insert("score", 10u32);
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.key_of(&player), Some(&"player"));
+        assert_eq!(storage.get_by_key::<String>(&"player").unwrap(), "hero");
+        assert_eq!(storage.get_by_key::<u32>(&"player"), None);
+
+        *storage.get_by_key_mut::<u32>(&"score").unwrap() += 5;
+        assert_eq!(storage.get_by_key::<u32>(&"score"), Some(&15));
+
+        let overwritten = storage.insert("player", String::from("villain"));
+        assert!(!storage.storage().contains(&player));
+        assert_eq!(storage.id_of(&"player"), Some(overwritten));
+        assert_eq!(storage.key_of(&player), None);
+        assert_eq!(storage.len(), 2);
+
+        assert_eq!(storage.remove_by_key::<u32>(&"player"), None);
+        assert!(storage.contains_key(&"player"));
+        assert_eq!(
+            storage.remove_by_key::<String>(&"player"),
+            Some(String::from("villain"))
+        );
+        assert!(!storage.contains_key(&"player"));
+        assert_eq!(storage.key_of(&overwritten), None);
+
+        let score = storage.id_of(&"score").unwrap();
+        storage.erase(&score);
+        assert!(storage.is_empty());
+        assert_eq!(storage.get_by_key::<u32>(&"score"), None);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    id::Id,
+    params::{Size, U32Size, Unique32, UniqueTag},
+    storage::Storage,
+};
+use std::collections::HashMap;
+
+// copy-on-write view: reads fall through to base, the first write of a value
+// clones it into the overlay and every later access sees the overlay copy
+pub struct LayeredStorage<'a, S: Size = U32Size, U: UniqueTag = Unique32> {
+    base: &'a Storage<S, U>,
+    overlay: Storage<S, U>,
+    copies: HashMap<Id<S, U>, Id<S, U>>,
+}
+
+impl<'a> LayeredStorage<'a> {
+    pub fn new(base: &'a Storage) -> Self {
+        Self::with_overlay(base, Storage::new())
+    }
+}
+
+impl<'a, S: Size, U: UniqueTag> LayeredStorage<'a, S, U> {
+    pub fn with_overlay(base: &'a Storage<S, U>, overlay: Storage<S, U>) -> Self {
+        Self {
+            base,
+            overlay,
+            copies: HashMap::new(),
+        }
+    }
+
+    pub fn get<T: Clone + 'static>(&self, id: &Id<S, U>) -> Option<&T> {
+        match self.copies.get(id) {
+            Some(copy) => self.overlay.try_get(copy),
+            None => self.base.try_get(id),
+        }
+    }
+
+    pub fn get_mut<T: Clone + 'static>(&mut self, id: &Id<S, U>) -> Option<&mut T> {
+        let copy = match self.copies.get(id) {
+            Some(copy) => *copy,
+            None => {
+                let copy = self.overlay.place(self.base.try_get::<T>(id)?.clone());
+                self.copies.insert(*id, copy);
+                copy
+            }
+        };
+
+        self.overlay.try_get_mut(&copy)
+    }
+
+    pub fn is_copied(&self, id: &Id<S, U>) -> bool {
+        self.copies.contains_key(id)
+    }
+
+    pub fn base(&self) -> &'a Storage<S, U> {
+        self.base
+    }
+
+    pub fn overlay(&self) -> &Storage<S, U> {
+        &self.overlay
+    }
+}
+
+mod tests {
+    #[test]
+    fn copy_on_write() {
+        use super::*;
+
+        let mut base = Storage::new();
+        let name = base.place(String::from("base"));
+        let count = base.place(1u32);
+
+        let mut layered = LayeredStorage::new(&base);
+        assert_eq!(layered.get::<String>(&name).unwrap(), "base");
+        assert_eq!(layered.get::<u32>(&count), Some(&1));
+        assert_eq!(layered.get::<u8>(&count), None);
+        assert!(layered.overlay().is_empty());
+
+        layered
+            .get_mut::<String>(&name)
+            .unwrap()
+            .push_str(" changed");
+        *layered.get_mut::<String>(&name).unwrap() += " twice";
+        assert!(layered.is_copied(&name));
+        assert!(!layered.is_copied(&count));
+        assert_eq!(layered.overlay().len(), 1);
+        assert_eq!(layered.get::<String>(&name).unwrap(), "base changed twice");
+        assert_eq!(layered.get_mut::<u8>(&count), None);
+
+        assert_eq!(base.get::<String>(&name), "base");
+        assert_eq!(*base.get::<u32>(&count), 1);
+    }
+}
@@ -1,8 +1,29 @@
 pub mod params;
+pub use crate::command_buffer::CommandBuffer;
+pub use crate::error::{PlaceError, RemoveError, TryReserveError};
+pub use crate::id::{Id, Tid, WeakId};
+pub use crate::keyed::KeyedStorage;
+pub use crate::layered::LayeredStorage;
 pub use crate::params::*;
-pub use crate::storage::Id;
+pub use crate::remap::IdRemap;
+pub use crate::report::{StorageReport, TokenReport, TypeReport};
+pub use crate::snapshot::Snapshot;
+pub use crate::storage::BucketRef;
+pub use crate::storage::BumpScope;
+pub use crate::storage::DrainFilter;
+pub use crate::storage::Neighbors;
+pub use crate::storage::Placer;
+pub use crate::storage::RemovalBatch;
 pub use crate::storage::Storage;
 
 mod bucket;
+mod command_buffer;
+mod error;
+mod id;
+mod keyed;
+mod layered;
+mod remap;
+mod report;
+mod snapshot;
 mod storage;
 mod token_bucket;
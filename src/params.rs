@@ -11,13 +11,26 @@ pub trait UniqueTag: Copy + Clone + Eq + PartialEq + Default + Hash + Debug {
 
     fn is_locked(&self) -> bool;
     fn mark_locked(&mut self);
+
+    // width in bytes of the raw value
+    fn bytes() -> usize;
+    fn to_raw(self) -> u128;
+    fn from_raw(raw: u128) -> Self;
 }
 
 macro_rules! impl_unique {
     ($S:tt, $T:tt) => {
         impl UniqueTag for $S {
+            // saturates at last(), a locked tag stays locked
             fn next(self) -> Self {
-                Self($T::min(self.0 + 1, self.last() as $T))
+                if self.is_locked() {
+                    return self;
+                }
+
+                let removed = self.0 & (1 << ($T::BITS - 1));
+                let generation = self.0 & !removed;
+                let last = $T::pow(2, $T::BITS - 1) - 1;
+                Self($T::min(generation.saturating_add(1), last) | removed)
             }
             fn last(self) -> usize {
                 ($T::pow(2, $T::BITS - 1) - 1) as _
@@ -43,6 +56,16 @@ macro_rules! impl_unique {
             fn mark_locked(&mut self) {
                 self.0 = $T::pow(2, $T::BITS - 1)
             }
+
+            fn bytes() -> usize {
+                core::mem::size_of::<$T>()
+            }
+            fn to_raw(self) -> u128 {
+                self.0 as _
+            }
+            fn from_raw(raw: u128) -> Self {
+                Self(raw as _)
+            }
         }
     };
 }
@@ -50,8 +73,11 @@ macro_rules! impl_unique {
 macro_rules! impl_repeat_in {
     ($S:tt, $T:tt) => {
         impl UniqueTag for $S {
+            // wraps around to 0 after the highest generation
             fn next(self) -> Self {
-                Self($T::min(self.0 + 1, self.last() as $T))
+                let removed = self.0 & (1 << ($T::BITS - 1));
+                let generation = self.0 & !removed;
+                Self((generation.wrapping_add(1) & !(1 << ($T::BITS - 1))) | removed)
             }
             fn last(self) -> usize {
                 $T::pow(2, $T::BITS - 1) as _
@@ -75,6 +101,16 @@ macro_rules! impl_repeat_in {
                 false
             }
             fn mark_locked(&mut self) {}
+
+            fn bytes() -> usize {
+                core::mem::size_of::<$T>()
+            }
+            fn to_raw(self) -> u128 {
+                self.0 as _
+            }
+            fn from_raw(raw: u128) -> Self {
+                Self(raw as _)
+            }
         }
     };
 }
@@ -106,15 +142,22 @@ impl_repeat_in!(RepeatIn64, u64);
 impl_repeat_in!(RepeatIn128, u128);
 
 pub trait Size:
-    Copy + Clone + Debug + Default + Eq + PartialEq + From<usize> + Into<usize>
+    Copy + Clone + Debug + Default + Eq + PartialEq + Hash + From<usize> + Into<usize>
 {
     fn max() -> usize;
+    // width in bytes of the raw value
+    fn bytes() -> usize;
+
+    // false where From<usize> would panic
+    fn fits(value: usize) -> bool {
+        value <= Self::max()
+    }
 }
 
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct U32Size(u32);
 
-#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct USize(usize);
 
 impl From<usize> for U32Size {
@@ -134,6 +177,9 @@ impl Size for U32Size {
     fn max() -> usize {
         u32::MAX as _
     }
+    fn bytes() -> usize {
+        core::mem::size_of::<u32>()
+    }
 }
 
 impl From<usize> for USize {
@@ -153,4 +199,50 @@ impl Size for USize {
     fn max() -> usize {
         usize::MAX
     }
+    fn bytes() -> usize {
+        core::mem::size_of::<usize>()
+    }
+    fn fits(value: usize) -> bool {
+        value <= u32::MAX as usize
+    }
+}
+
+mod tests {
+    #[test]
+    fn unique_next_saturates() {
+        use super::*;
+
+        let last = Unique32(u32::pow(2, 31) - 1);
+        assert_eq!(last.next(), last);
+        assert_eq!(Unique32(5).next(), Unique32(6));
+
+        let mut locked = Unique32::default();
+        locked.mark_locked();
+        assert!(locked.next().is_locked());
+
+        let mut removed = last;
+        removed.set_removed(true);
+        assert_eq!(removed.0, u32::MAX);
+        assert_eq!(removed.next(), removed);
+
+        let max = Unique128(u128::MAX);
+        assert_eq!(max.next(), max);
+    }
+
+    #[test]
+    fn repeat_in_next_wraps() {
+        use super::*;
+
+        let last = RepeatIn32(u32::pow(2, 31) - 1);
+        assert_eq!(last.next(), RepeatIn32(0));
+        assert!(!last.next().is_removed());
+
+        let mut removed = last;
+        removed.set_removed(true);
+        let next = removed.next();
+        assert!(next.is_removed());
+        assert_eq!(next.current(), 1 << 31);
+
+        assert_eq!(RepeatIn64(u64::MAX).next(), RepeatIn64(1 << 63));
+    }
 }
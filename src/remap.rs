@@ -0,0 +1,104 @@
+use crate::{
+    id::Id,
+    params::{Size, U32Size, Unique32, UniqueTag},
+};
+use std::collections::HashMap;
+
+// old id -> new id, for rewriting ids kept outside of the storage, NewS differs
+// from S only after Storage::resize_to
+pub struct IdRemap<S: Size = U32Size, U: UniqueTag = Unique32, NewS: Size = S> {
+    ids: HashMap<Id<S, U>, Id<NewS, U>>,
+}
+
+impl<S: Size, U: UniqueTag> IdRemap<S, U> {
+    // unmapped ids become Id::dangling()
+    pub fn apply_to(&self, ids: &mut [Id<S, U>]) {
+        for id in ids.iter_mut() {
+            *id = self.get(*id).unwrap_or_else(Id::dangling);
+        }
+    }
+}
+
+impl<S: Size, U: UniqueTag, NewS: Size> IdRemap<S, U, NewS> {
+    pub fn new() -> Self {
+        Self {
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, old: Id<S, U>, new: Id<NewS, U>) {
+        self.ids.insert(old, new);
+    }
+
+    pub fn get(&self, old: Id<S, U>) -> Option<Id<NewS, U>> {
+        self.ids.get(&old).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl<S: Size, U: UniqueTag, NewS: Size> Default for IdRemap<S, U, NewS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Size, U: UniqueTag, NewS: Size> FromIterator<(Id<S, U>, Id<NewS, U>)>
+    for IdRemap<S, U, NewS>
+{
+    fn from_iter<I: IntoIterator<Item = (Id<S, U>, Id<NewS, U>)>>(iter: I) -> Self {
+        Self {
+            ids: iter.into_iter().collect(),
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn apply_to() {
+        use super::*;
+        use crate::Storage;
+
+        let mut storage = Storage::new();
+        let old = (0..4u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let new = (0..4u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+
+        let remap = old
+            .iter()
+            .copied()
+            .zip(new.iter().copied())
+            .take(3)
+            .collect::<IdRemap>();
+        assert_eq!(remap.len(), 3);
+        assert_eq!(remap.get(old[1]), Some(new[1]));
+        assert_eq!(remap.get(old[3]), None);
+
+        let mut ids = vec![old[2], old[0], old[3], old[2]];
+        remap.apply_to(&mut ids);
+        assert_eq!(ids, vec![new[2], new[0], Id::dangling(), new[2]]);
+        assert!(!storage.contains(&ids[2]));
+    }
+
+    #[test]
+    fn apply_to_usize() {
+        use super::*;
+        use crate::{params::USize, Storage};
+
+        let mut storage = Storage::new_with_tag_and_size::<USize, Unique32>();
+        let old = storage.place(0u32);
+        let new = storage.place(1u32);
+        let remap = [(old, new)].into_iter().collect::<IdRemap<USize>>();
+
+        let mut ids = vec![new, old];
+        remap.apply_to(&mut ids);
+        assert_eq!(ids, vec![Id::dangling(), new]);
+        assert!(!storage.contains(&ids[0]));
+        assert!(storage.try_get::<u32>(&ids[0]).is_none());
+    }
+}
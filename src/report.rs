@@ -0,0 +1,137 @@
+use crate::{
+    params::{Size, UniqueTag},
+    storage::Storage,
+};
+use core::fmt::{self, Display};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TypeReport {
+    pub type_name: &'static str,
+    pub len: usize,
+    pub capacity: usize,
+    pub high_water: usize,
+    // share of the capacity that is not used, 0.0 for an empty bucket
+    pub fragmentation: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenReport {
+    pub total: usize,
+    pub live: usize,
+    pub free: usize,
+    pub locked: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StorageReport {
+    pub types: Vec<TypeReport>,
+    pub tokens: TokenReport,
+}
+
+impl StorageReport {
+    pub(crate) fn new<S: Size, U: UniqueTag>(storage: &Storage<S, U>) -> Self {
+        let types = storage
+            .buckets()
+            .map(|bucket| TypeReport {
+                type_name: bucket.type_name(),
+                len: bucket.len(),
+                capacity: bucket.capacity(),
+                high_water: bucket.high_water(),
+                fragmentation: bucket.fragmentation(),
+            })
+            .collect();
+
+        let mut tokens = TokenReport::default();
+        for tag in storage.tokens().tags() {
+            tokens.total += 1;
+            if tag.is_locked() {
+                tokens.locked += 1;
+            } else if tag.is_removed() {
+                tokens.free += 1;
+            } else {
+                tokens.live += 1;
+            }
+        }
+
+        Self { types, tokens }
+    }
+}
+
+impl Display for StorageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .types
+            .iter()
+            .map(|report| report.type_name.len())
+            .max()
+            .unwrap_or(0)
+            .max("type".len());
+
+        writeln!(
+            f,
+            "{:<width$} | {:>10} | {:>10} | {:>10} | {:>13}",
+            "type", "len", "capacity", "high water", "fragmentation"
+        )?;
+        for report in self.types.iter() {
+            writeln!(
+                f,
+                "{:<width$} | {:>10} | {:>10} | {:>10} | {:>12.1}%",
+                report.type_name,
+                report.len,
+                report.capacity,
+                report.high_water,
+                report.fragmentation * 100.0
+            )?;
+        }
+        write!(
+            f,
+            "tokens: {} total, {} live, {} free, {} locked",
+            self.tokens.total, self.tokens.live, self.tokens.free, self.tokens.locked
+        )
+    }
+}
+
+mod tests {
+    #[test]
+    fn report() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        for id in ids.iter().take(4) {
+            storage.remove::<u32>(id);
+        }
+        storage.place(String::from("report"));
+        storage.place(1u8);
+        storage.place(2u8);
+
+        let report = storage.report();
+        assert_eq!(report.types.len(), 3);
+
+        let u32s = &report.types[0];
+        assert_eq!(u32s.type_name, "u32");
+        assert_eq!((u32s.len, u32s.high_water), (6, 10));
+        assert_eq!(u32s.capacity, storage.capacity::<u32>());
+        assert!(u32s.fragmentation > 0.0);
+
+        assert_eq!(report.types[1].type_name, core::any::type_name::<String>());
+        assert_eq!(report.types[1].len, 1);
+        assert_eq!(report.types[2].type_name, "u8");
+        assert_eq!(report.types[2].len, 2);
+
+        // the removed tokens are reused by the later places
+        assert_eq!(
+            report.tokens,
+            TokenReport {
+                total: 10,
+                live: 9,
+                free: 1,
+                locked: 0,
+            }
+        );
+
+        let table = report.to_string();
+        assert!(table.contains("u32"));
+        assert!(table.contains("tokens: 10 total, 9 live, 1 free, 0 locked"));
+    }
+}
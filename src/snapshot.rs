@@ -0,0 +1,69 @@
+use crate::{
+    id::Id,
+    params::{Size, U32Size, Unique32, UniqueTag},
+    storage::Storage,
+};
+
+// read-only view of a storage for code that must not mutate it
+#[derive(Copy, Clone)]
+pub struct Snapshot<'a, S: Size = U32Size, U: UniqueTag = Unique32> {
+    storage: &'a Storage<S, U>,
+}
+
+impl<'a, S: Size, U: UniqueTag> Snapshot<'a, S, U> {
+    pub(crate) fn new(storage: &'a Storage<S, U>) -> Self {
+        Self { storage }
+    }
+
+    pub fn get<T: 'static>(&self, id: &Id<S, U>) -> &'a T {
+        self.storage.get(id)
+    }
+
+    pub fn try_get<T: 'static>(&self, id: &Id<S, U>) -> Option<&'a T> {
+        self.storage.try_get(id)
+    }
+
+    pub fn contains(&self, id: &Id<S, U>) -> bool {
+        self.storage.contains(id)
+    }
+
+    pub fn contains_exact<T: 'static>(&self, id: &Id<S, U>) -> bool {
+        self.storage.contains_exact::<T>(id)
+    }
+
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = &'a T> {
+        self.storage.iter::<T>()
+    }
+}
+
+mod tests {
+    #[test]
+    fn query_through_snapshot() {
+        use super::*;
+
+        fn total(snapshot: Snapshot<'_>, ids: &[Id<U32Size, Unique32>]) -> u32 {
+            ids.iter()
+                .filter(|id| snapshot.contains(id))
+                .map(|id| *snapshot.get::<u32>(id))
+                .sum()
+        }
+
+        let mut storage = Storage::new();
+        let ids = (1..=4)
+            .map(|value| storage.place::<u32>(value))
+            .collect::<Vec<_>>();
+        storage.place::<u8>(100);
+        storage.erase(&ids[0]);
+
+        let snapshot = storage.snapshot();
+        assert_eq!(total(snapshot, &ids), 9);
+        assert_eq!(snapshot.iter::<u32>().sum::<u32>(), 9);
+        assert_eq!(
+            snapshot.iter::<u8>().copied().collect::<Vec<_>>(),
+            vec![100]
+        );
+        assert_eq!(snapshot.iter::<u64>().count(), 0);
+        assert!(snapshot.contains_exact::<u32>(&ids[1]));
+        assert!(snapshot.try_get::<u8>(&ids[1]).is_none());
+    }
+}
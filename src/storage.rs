@@ -1,25 +1,84 @@
 use crate::{
-    bucket::Bucket,
+    bucket::{cell_layout, Bucket, DEFAULT_GROWTH_FACTOR, DEFAULT_START_CAPACITY},
+    command_buffer::CommandBuffer,
+    error::{PlaceError, RemoveError, TryReserveError},
+    id::{Id, Tid, WeakId},
     params::{Size, Unique32, UniqueTag},
-    token_bucket::TokenBucket,
+    remap::IdRemap,
+    report::StorageReport,
+    snapshot::Snapshot,
+    token_bucket::{Location, TokenBucket},
     U32Size,
 };
-use core::any::TypeId;
-use std::collections::HashMap;
+use core::{
+    alloc::Layout,
+    any::{Any, TypeId},
+    marker::PhantomData,
+    mem::size_of,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+};
+
+type PlaceHook<S, U> = Box<dyn FnMut(*const u8, Id<S, U>)>;
+
+// (live, stale)
+type LiveAndStale<S, U> = (Vec<Id<S, U>>, Vec<Id<S, U>>);
+
+// (values, old id -> index into values)
+type Remapped<S, U> = (Vec<(TypeId, Box<dyn Any>)>, Vec<(Id<S, U>, usize)>);
+
+const HOT_TYPES: usize = 4;
+
+// no-op for storages without discriminants
+#[cfg(feature = "deterministic")]
+fn by_token_index<S: Size, V>(iter: impl Iterator<Item = (S, V)>) -> std::vec::IntoIter<(S, V)> {
+    let mut cells = iter.collect::<Vec<_>>();
+    cells.sort_unstable_by_key(|(token_index, _)| (*token_index).into());
+    cells.into_iter()
+}
+
+fn set_discriminant(discriminants: &mut Option<Vec<u16>>, token_index: usize, discriminant: u16) {
+    if let Some(discriminants) = discriminants.as_mut() {
+        if token_index >= discriminants.len() {
+            discriminants.resize(token_index + 1, 0);
+        }
+        discriminants[token_index] = discriminant;
+    }
+}
+
+// ids of a capped type in creation order, may hold ids that were removed since
+struct Eviction<S: Size, U: UniqueTag> {
+    cap: usize,
+    order: VecDeque<Id<S, U>>,
+}
 
 pub struct Storage<S: Size = U32Size, U: UniqueTag = Unique32> {
     tokens: TokenBucket<S, U>,
     buckets: Vec<(TypeId, Bucket<S>)>,
     bucket_indexes: HashMap<TypeId, S>,
+    bucket_capacity: usize,
+    growth_factor: f64,
+    len: usize,
+    hot_types: [Option<(TypeId, S)>; HOT_TYPES],
+    place_hooks: HashMap<TypeId, PlaceHook<S, U>>,
+    evictions: HashMap<TypeId, Eviction<S, U>>,
+    // indexed by token index, kept beside the cells so storages without them pay nothing
+    discriminants: Option<Vec<u16>>,
+    #[cfg(feature = "dirty-tracking")]
+    dirty: HashMap<TypeId, HashSet<Id<S, U>>>,
+    // where the last id of each token index was removed, dropped once place reissues it
+    #[cfg(feature = "track-removals")]
+    removals: HashMap<S, (U, &'static core::panic::Location<'static>)>,
+    // set while buckets are cleared or dropped, placing then is a bug
+    #[cfg(debug_assertions)]
+    tearing_down: bool,
 }
 
 impl Storage<U32Size, Unique32> {
     pub fn new() -> Self {
-        Self {
-            tokens: TokenBucket::new(),
-            buckets: Vec::new(),
-            bucket_indexes: HashMap::new(),
-        }
+        Storage::new_with_tag_and_size()
     }
 }
 
@@ -29,367 +88,4455 @@ impl Storage {
             tokens: TokenBucket::new(),
             buckets: Vec::new(),
             bucket_indexes: HashMap::new(),
+            bucket_capacity: DEFAULT_START_CAPACITY,
+            growth_factor: DEFAULT_GROWTH_FACTOR,
+            len: 0,
+            hot_types: [None; HOT_TYPES],
+            place_hooks: HashMap::new(),
+            evictions: HashMap::new(),
+            discriminants: None,
+            #[cfg(feature = "dirty-tracking")]
+            dirty: HashMap::new(),
+            #[cfg(feature = "track-removals")]
+            removals: HashMap::new(),
+            #[cfg(debug_assertions)]
+            tearing_down: false,
+        }
+    }
+
+    // name of the smallest Size that can hold that many elements, token indexes
+    // and in-bucket indexes both have to fit it
+    pub fn recommended_size(expected_max_elements: usize) -> &'static str {
+        if expected_max_elements <= U32Size::max() {
+            "U32Size"
+        } else {
+            "USize"
         }
     }
 }
 
 impl<S: Size, U: UniqueTag> Storage<S, U> {
-    pub fn place<T: 'static>(&mut self, data: T) -> Id<S, U> {
-        let type_id = TypeId::of::<T>();
-        let bucket_index = *self
-            .bucket_indexes
-            .entry(type_id)
-            .or_insert(self.buckets.len().into());
+    // capacity allocated by the first place into a new bucket
+    pub fn with_bucket_capacity(mut self, capacity: usize) -> Self {
+        assert_ne!(capacity, 0);
+        self.bucket_capacity = capacity;
+        self
+    }
 
-        if bucket_index == self.buckets.len().into() {
-            assert_ne!(self.buckets.len(), S::max());
-            self.buckets.push((type_id, Bucket::new::<T>()));
+    // tokens are shared by all types, sizing them up front avoids regrowing on early places
+    pub fn with_token_capacity(mut self, capacity: usize) -> Self {
+        self.tokens
+            .reserve_exact(capacity.saturating_sub(self.tokens.len()));
+        self
+    }
+
+    // sizes the type directory, with register no type lookup structure grows later
+    pub fn with_type_capacity(mut self, types: usize) -> Self {
+        let additional = types.saturating_sub(self.buckets.len());
+        self.buckets.reserve_exact(additional);
+        self.bucket_indexes.reserve(additional);
+        self
+    }
+
+    // creates the empty bucket of T up front, see ensure_bucket
+    pub fn register<T: 'static>(mut self) -> Self {
+        self.ensure_bucket::<T>();
+        self
+    }
+
+    // creates the bucket of T with room for n values, token room adds up over chained calls
+    pub fn with_capacity_for<T: 'static>(mut self, n: usize) -> Self {
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        self.buckets[bucket_index.into()].1.reserve_exact(n);
+        self.tokens.reserve_exact(self.tokens.spare_capacity() + n);
+        self
+    }
+
+    // every place records a discriminant, 0 unless placed with place_with_discriminant
+    pub fn with_discriminants(mut self) -> Self {
+        self.discriminants = Some(vec![0; self.tokens.len()]);
+        self
+    }
+
+    pub fn token_capacity(&self) -> usize {
+        self.tokens.capacity()
+    }
+
+    pub fn growth_factor(&self) -> f64 {
+        self.growth_factor
+    }
+
+    // applies to every later grow of every bucket, clamped to 1.1..=4.0
+    pub fn set_growth_factor(&mut self, factor: f64) {
+        self.growth_factor = factor.clamp(1.1, 4.0);
+        for (_, bucket) in self.buckets.iter_mut() {
+            bucket.set_growth_factor(self.growth_factor);
         }
+    }
+
+    pub fn place<T: 'static>(&mut self, data: T) -> Id<S, U> {
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        unsafe { self.place_at_unchecked(bucket_index, data) }
+    }
+
+    // hands data back instead of panicking once the bucket of T or the tokens are full
+    pub fn try_place<T: 'static>(&mut self, data: T) -> Result<Id<S, U>, PlaceError<T>> {
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        unsafe { self.try_place_at_unchecked(bucket_index, data) }
+    }
 
+    // grows the bucket of T to expected_total first if it is smaller, never shrinks it
+    pub fn place_hint<T: 'static>(&mut self, data: T, expected_total: usize) -> Id<S, U> {
+        let bucket_index = self.bucket_index_or_insert::<T>();
         let bucket = &mut self.buckets[bucket_index.into()].1;
+        if bucket.capacity() < expected_total {
+            bucket.reserve_exact(expected_total - bucket.len());
+        }
 
-        match unsafe { bucket.push_unchecked(data) } {
-            Ok(inbucket_index) => {
-                let (token_index, tag) = self.tokens.create(bucket_index, inbucket_index);
-                unsafe {
-                    bucket.set_token_index_unchecked::<T>(inbucket_index, token_index);
-                }
-                Id::new(token_index, tag)
+        unsafe { self.place_at_unchecked(bucket_index, data) }
+    }
+
+    // the storage has to be built with_discriminants
+    pub fn place_with_discriminant<T: 'static>(&mut self, data: T, discriminant: u16) -> Id<S, U> {
+        assert!(self.discriminants.is_some());
+        let id = self.place(data);
+        set_discriminant(
+            &mut self.discriminants,
+            id.token_index().into(),
+            discriminant,
+        );
+        id
+    }
+
+    pub fn discriminant(&self, id: &Id<S, U>) -> Option<u16> {
+        let discriminants = self.discriminants.as_ref()?;
+        if !self.contains(id) {
+            return None;
+        }
+
+        Some(discriminants[id.token_index().into()])
+    }
+
+    pub fn place_into<T: 'static, V: Into<T>>(&mut self, value: V) -> Id<S, U> {
+        self.place::<T>(value.into())
+    }
+
+    pub fn place_n_with<T: 'static>(
+        &mut self,
+        n: usize,
+        mut f: impl FnMut(usize) -> T,
+    ) -> Vec<Id<S, U>> {
+        self.reserve::<T>(n);
+        let bucket_index = self.bucket_index_or_insert::<T>();
+
+        (0..n)
+            .map(|index| unsafe { self.place_at_unchecked(bucket_index, f(index)) })
+            .collect()
+    }
+
+    // creates the bucket of T without placing anything
+    pub fn ensure_bucket<T: 'static>(&mut self) {
+        self.bucket_index_or_insert::<T>();
+    }
+
+    // grows every existing bucket to at least per_type_capacity
+    pub fn prewarm(&mut self, per_type_capacity: usize) {
+        for (_, bucket) in self.buckets.iter_mut() {
+            if bucket.capacity() < per_type_capacity {
+                bucket.reserve_exact(per_type_capacity - bucket.len());
             }
-            Err(_) => panic!(),
         }
     }
 
-    pub fn place_in_bucket<T: 'static>(bucket_ref: BucketRef<'_, S, U>, data: T) -> Id<S, U> {
-        let type_id = TypeId::of::<T>();
-        let bucket_index = *bucket_ref.entry.or_insert(bucket_ref.data.len().into());
+    pub fn reserve<T: 'static>(&mut self, additional: usize) {
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        self.buckets[bucket_index.into()].1.reserve(additional);
+        self.tokens.reserve(additional);
+    }
+
+    // like reserve but without rounding up by the growth factor
+    pub fn reserve_exact<T: 'static>(&mut self, additional: usize) {
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        self.buckets[bucket_index.into()]
+            .1
+            .reserve_exact(additional);
+        self.tokens.reserve(additional);
+    }
+
+    pub fn try_reserve<T: 'static>(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        self.buckets[bucket_index.into()]
+            .1
+            .try_reserve(additional)?;
+        self.tokens.try_reserve(additional)
+    }
+
+    // conservative, may return false while reused tokens would still fit
+    pub fn can_place_without_growth<T: 'static>(&self, count: usize) -> bool {
+        let spare = self
+            .bucket_of::<T>()
+            .map_or(0, |bucket| bucket.capacity() - bucket.len());
+        spare >= count && self.tokens.spare_capacity() >= count
+    }
+
+    // stores the box itself, the value is not moved out of its allocation, values
+    // placed this way live in the bucket of Box<T> and are read with get_boxed
+    pub fn place_boxed<T: 'static>(&mut self, value: Box<T>) -> Id<S, U> {
+        self.place(value)
+    }
+
+    pub fn get_boxed<T: 'static>(&self, id: &Id<S, U>) -> Option<&T> {
+        self.try_get::<Box<T>>(id).map(|value| &**value)
+    }
+
+    pub fn place_typed<T: 'static>(&mut self, data: T) -> Tid<T, S, U> {
+        Tid::new(self.place(data))
+    }
+
+    // the reference is for finishing initialization right after placing
+    pub fn place_typed_mut<T: 'static>(&mut self, data: T) -> (Tid<T, S, U>, &mut T) {
+        let id = self.place(data);
+        (Tid::new(id), self.get_mut(&id))
+    }
+
+    // values placed through the scope are erased when it is dropped, the storage
+    // is read only meanwhile so they always sit at the end of the bucket
+    pub fn bump_scope<T: 'static>(&mut self, count: usize) -> BumpScope<'_, T, S, U> {
+        self.reserve::<T>(count);
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        BumpScope {
+            start_len: self.buckets[bucket_index.into()].1.len(),
+            storage: self,
+            bucket_index,
+            count,
+            phantom: PhantomData,
+        }
+    }
 
-        if bucket_index == bucket_ref.data.len().into() {
-            assert_ne!(bucket_ref.data.len(), S::max());
-            bucket_ref.data.push((type_id, Bucket::new::<T>()));
+    // queued ids are erased when the batch is dropped
+    pub fn removal_batch(&mut self) -> RemovalBatch<'_, S, U> {
+        RemovalBatch {
+            storage: self,
+            ids: vec![],
         }
+    }
+
+    pub fn placer<T: 'static>(&mut self) -> Placer<'_, T, S, U> {
+        Placer {
+            bucket_index: self.bucket_index_or_insert::<T>(),
+            storage: self,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn on_place<T: 'static>(&mut self, mut f: impl FnMut(&T, Id<S, U>) + 'static) {
+        self.place_hooks.insert(
+            TypeId::of::<T>(),
+            Box::new(move |data, id| f(unsafe { &*data.cast::<T>() }, id)),
+        );
+    }
+
+    // place_with_eviction keeps at most cap values of T, values already placed
+    // count as older than any placed later, in bucket order. Every place records the
+    // order, but only place_with_eviction evicts, so values of a capped type placed
+    // any other way (place, place_into, Placer, BucketRef) grow the bucket past cap
+    pub fn set_eviction_cap<T: 'static>(&mut self, cap: usize) {
+        assert_ne!(cap, 0);
+        let order = match self.bucket_of::<T>() {
+            Some(bucket) => (0..bucket.len())
+                .map(|inbucket_index| unsafe { self.id_at_unchecked(bucket, inbucket_index) })
+                .collect(),
+            None => VecDeque::new(),
+        };
+        self.evictions
+            .insert(TypeId::of::<T>(), Eviction { cap, order });
+    }
 
-        let bucket = &mut bucket_ref.data[bucket_index.into()].1;
+    pub fn remove_eviction_cap<T: 'static>(&mut self) {
+        self.evictions.remove(&TypeId::of::<T>());
+    }
+
+    // removes the oldest value of T first when the cap is reached, same as place
+    // when no cap is set
+    pub fn place_with_eviction<T: 'static>(&mut self, data: T) -> (Id<S, U>, Option<T>) {
+        let type_id = TypeId::of::<T>();
+        let len = self.bucket_of::<T>().map_or(0, |bucket| bucket.len());
 
-        match unsafe { bucket.push_unchecked(data) } {
-            Ok(inbucket_index) => {
-                let (token_index, tag) = bucket_ref.tokens.create(bucket_index, inbucket_index);
-                unsafe {
-                    bucket.set_token_index_unchecked::<T>(inbucket_index, token_index);
+        let mut evicted = None;
+        if self
+            .evictions
+            .get(&type_id)
+            .is_some_and(|eviction| len >= eviction.cap)
+        {
+            while let Some(oldest) = self
+                .evictions
+                .get_mut(&type_id)
+                .and_then(|eviction| eviction.order.pop_front())
+            {
+                evicted = self.remove_untracked::<T>(&oldest).ok();
+                if evicted.is_some() {
+                    break;
                 }
-                Id::new(token_index, tag)
             }
-            Err(_) => panic!(),
         }
+
+        (self.place(data), evicted)
+    }
+
+    // caches the bucket of T in front of the type map, the oldest pin is evicted
+    pub fn pin_hot_type<T: 'static>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if self.hot_bucket_index(type_id).is_some() {
+            return;
+        }
+
+        let bucket_index = self.bucket_index_or_insert::<T>();
+        self.hot_types.rotate_right(1);
+        self.hot_types[0] = Some((type_id, bucket_index));
     }
 
+    #[cfg_attr(feature = "track-removals", track_caller)]
     pub fn remove<T: 'static>(&mut self, id: &Id<S, U>) -> Option<T> {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                if token.tag().is_removed() || token.tag().is_locked() {
-                    return None;
-                }
+        self.check_double_removal(id);
+        self.try_remove(id).ok()
+    }
+
+    // like remove, but tells a stale id from one of another type and never panics
+    #[cfg_attr(feature = "track-removals", track_caller)]
+    pub fn try_remove<T: 'static>(&mut self, id: &Id<S, U>) -> Result<T, RemoveError> {
+        let data = self.remove_untracked(id)?;
+        self.record_removal(id);
+        Ok(data)
+    }
+
+    fn remove_untracked<T: 'static>(&mut self, id: &Id<S, U>) -> Result<T, RemoveError> {
+        let Some(location) = self.live_location(id) else {
+            let locked = self
+                .tokens
+                .try_get_token(id.token_index())
+                .is_some_and(|token| token.tag().is_locked());
+            return Err(if locked {
+                RemoveError::Locked
+            } else {
+                RemoveError::Stale
+            });
+        };
+        let (type_id, bucket) = &self.buckets[location.bucket_index().into()];
+        if TypeId::of::<T>() != *type_id {
+            return Err(RemoveError::TypeMismatch {
+                expected: core::any::type_name::<T>(),
+                found: bucket.type_name(),
+            });
+        }
+
+        Ok(unsafe { self.remove_at_unchecked(location.bucket_index(), location.inbucket_index()) })
+    }
 
-                let location = unsafe { *token.location() };
-                match self.buckets.get_mut(location.bucket_index().into()) {
-                    Some((type_id, bucket)) => {
-                        if TypeId::of::<T>() != *type_id {
-                            return None;
-                        }
+    // panics when id was already removed through remove or erase, naming both callers
+    #[cfg_attr(feature = "track-removals", track_caller)]
+    #[cfg_attr(not(feature = "track-removals"), allow(unused_variables))]
+    fn check_double_removal(&self, id: &Id<S, U>) {
+        #[cfg(feature = "track-removals")]
+        if let Some((_, first)) = self
+            .removals
+            .get(&id.token_index())
+            .filter(|(tag, _)| *tag == id.tag())
+        {
+            panic!(
+                "{:?} removed twice, first at {}, again at {}",
+                id,
+                first,
+                core::panic::Location::caller()
+            );
+        }
+    }
 
-                        self.tokens.mark_removed(id.token_index());
+    #[cfg_attr(feature = "track-removals", track_caller)]
+    #[cfg_attr(not(feature = "track-removals"), allow(unused_variables))]
+    fn record_removal(&mut self, id: &Id<S, U>) {
+        #[cfg(feature = "track-removals")]
+        self.removals.insert(
+            id.token_index(),
+            (id.tag(), core::panic::Location::caller()),
+        );
+    }
 
-                        let (data, token_index_for_swap) =
-                            unsafe { bucket.swap_remove_unchecked::<T>(location.inbucket_index()) };
+    #[cfg_attr(feature = "track-removals", track_caller)]
+    pub fn remove_typed<T: 'static>(&mut self, id: &Tid<T, S, U>) -> Option<T> {
+        self.remove(id.id())
+    }
 
-                        if let Some(token_index) = token_index_for_swap {
-                            self.tokens
-                                .set_inbucket_index(token_index, location.inbucket_index())
-                        }
+    // the id stays valid
+    pub fn replace<T: 'static>(&mut self, id: &Id<S, U>, data: T) -> Option<T> {
+        self.try_get_mut(id)
+            .map(|value| core::mem::replace(value, data))
+    }
 
-                        Some(data)
-                    }
-                    None => None,
+    // like replace, but the value gets a new generation in the same slot, so every
+    // copy of the old id goes stale
+    pub fn exchange<T: 'static>(&mut self, id: &Id<S, U>, data: T) -> Option<(Id<S, U>, T)> {
+        let location = self.live_location(id)?;
+        let discriminant = self.discriminant(id).unwrap_or(0);
+        let (type_id, bucket) = &mut self.buckets[location.bucket_index().into()];
+        if TypeId::of::<T>() != *type_id {
+            return None;
+        }
+
+        let (token_index, tag) = self.tokens.reissue(id.token_index());
+        if token_index != id.token_index() {
+            unsafe {
+                bucket.set_token_index_unchecked::<T>(location.inbucket_index(), token_index)
+            };
+            set_discriminant(&mut self.discriminants, token_index.into(), discriminant);
+        }
+        let data = core::mem::replace(
+            unsafe { bucket.get_mut_unchecked(location.inbucket_index()) },
+            data,
+        );
+
+        let id = Id::new(token_index, tag);
+        #[cfg(feature = "dirty-tracking")]
+        self.dirty.entry(*type_id).or_default().insert(id);
+
+        // counts as newly placed, the old id left in the order is skipped as removed
+        self.queue_for_eviction::<T>(id);
+
+        Some((id, data))
+    }
+
+    // linear scan over the bucket of T, O(n) per call
+    pub fn place_unique_by<T: 'static>(
+        &mut self,
+        data: T,
+        eq: impl Fn(&T, &T) -> bool,
+    ) -> Id<S, U> {
+        if let Some(bucket_index) = self.bucket_index_of::<T>() {
+            let bucket = &self.buckets[bucket_index.into()].1;
+            for inbucket_index in 0..bucket.len() {
+                if eq(
+                    unsafe { bucket.get_unchecked(inbucket_index.into()) },
+                    &data,
+                ) {
+                    return unsafe { self.id_at_unchecked(bucket, inbucket_index) };
                 }
             }
-            None => None,
         }
+
+        self.place(data)
     }
 
-    pub fn erase(&mut self, id: &Id<S, U>) {
-        if let Some(token) = self.tokens.try_get_token(id.token_index()) {
-            if token.tag().is_removed() || token.tag().is_locked() {
-                return;
+    pub fn remove_where<T: 'static, F: Fn(&T) -> bool>(&mut self, pred: F) -> Vec<T> {
+        let mut removed = Vec::new();
+        let bucket_index = match self.bucket_index_of::<T>() {
+            Some(bucket_index) => bucket_index,
+            None => return removed,
+        };
+
+        let mut inbucket_index = 0;
+        while inbucket_index < self.buckets[bucket_index.into()].1.len() {
+            let bucket = &self.buckets[bucket_index.into()].1;
+            if pred(unsafe { bucket.get_unchecked(inbucket_index.into()) }) {
+                // the last element is swapped into inbucket_index, so it is checked next
+                removed
+                    .push(unsafe { self.remove_at_unchecked(bucket_index, inbucket_index.into()) });
+            } else {
+                inbucket_index += 1;
             }
+        }
 
-            let location = unsafe { *token.location() };
-            if let Some((_, bucket)) = self.buckets.get_mut(location.bucket_index().into()) {
-                self.tokens.mark_removed(id.token_index());
+        removed
+    }
+
+    pub fn drain_filter<T: 'static, F: FnMut(&mut T) -> bool>(
+        &mut self,
+        pred: F,
+    ) -> DrainFilter<'_, T, F, S, U> {
+        DrainFilter {
+            bucket_index: self.bucket_index_of::<T>(),
+            storage: self,
+            inbucket_index: 0,
+            pred,
+            phantom: PhantomData,
+        }
+    }
 
-                let token_index_for_swap =
-                    unsafe { bucket.swap_erase_unchecked(location.inbucket_index()) };
+    #[cfg_attr(feature = "track-removals", track_caller)]
+    pub fn erase(&mut self, id: &Id<S, U>) {
+        self.check_double_removal(id);
+        if let Some(location) = self.live_location(id) {
+            unsafe { self.erase_at_unchecked(location.bucket_index(), location.inbucket_index()) }
+            self.record_removal(id);
+        }
+    }
 
-                if let Some(token_index) = token_index_for_swap {
-                    self.tokens
-                        .set_inbucket_index(token_index, location.inbucket_index())
+    // erases every value of every type whose id is not in keep
+    pub fn retain_ids(&mut self, keep: &HashSet<Id<S, U>>) {
+        for bucket_index in 0..self.buckets.len() {
+            let mut inbucket_index = 0;
+            while inbucket_index < self.buckets[bucket_index].1.len() {
+                let id =
+                    unsafe { self.id_at_unchecked(&self.buckets[bucket_index].1, inbucket_index) };
+                if keep.contains(&id) {
+                    inbucket_index += 1;
+                } else {
+                    // the last element is swapped into inbucket_index, so it is checked next
+                    unsafe { self.erase_at_unchecked(bucket_index.into(), inbucket_index.into()) };
                 }
             }
         }
     }
 
+    #[track_caller]
     pub fn get<T: 'static>(&self, id: &Id<S, U>) -> &T {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                let location = unsafe { *token.location() };
+        match self.live_location(id) {
+            Some(location) => {
                 let (type_id, bucket) = &self.buckets[location.bucket_index().into()];
                 if TypeId::of::<T>() != *type_id {
-                    panic!();
+                    self.get_failed::<T>(id);
                 }
 
                 unsafe { bucket.get_unchecked(location.inbucket_index()) }
             }
-            None => panic!(),
+            None => self.get_failed::<T>(id),
+        }
+    }
+
+    // tells apart why get could not resolve id to a T
+    #[cold]
+    #[track_caller]
+    fn get_failed<T: 'static>(&self, id: &Id<S, U>) -> ! {
+        let token_index = id.token_index().into();
+        let Some(token) = self.tokens.try_get_token(id.token_index()) else {
+            panic!("unknown token index {token_index}");
+        };
+        if token.tag().is_locked() {
+            panic!("value at token index {token_index} was removed, the token is locked");
+        }
+
+        match self.live_location(id) {
+            Some(location) => panic!(
+                "type mismatch (expected {}, found {})",
+                core::any::type_name::<T>(),
+                self.buckets[location.bucket_index().into()].1.type_name()
+            ),
+            None => panic!("value at token index {token_index} was removed"),
+        }
+    }
+
+    // whatever currently occupies the slot, if it is a T
+    pub fn get_weak<T: 'static>(&self, id: &WeakId<S>) -> Option<&T> {
+        let token = self.tokens.try_get_token(id.token_index())?;
+        let location = token.try_location()?;
+        match &self.buckets[location.bucket_index().into()] {
+            (type_id, bucket) if *type_id == TypeId::of::<T>() => {
+                bucket.try_get(location.inbucket_index())
+            }
+            _ => None,
         }
     }
 
+    #[track_caller]
+    pub fn get_typed<T: 'static>(&self, id: &Tid<T, S, U>) -> &T {
+        self.get(id.id())
+    }
+
+    #[track_caller]
+    pub fn get_typed_mut<T: 'static>(&mut self, id: &Tid<T, S, U>) -> &mut T {
+        self.get_mut(id.id())
+    }
+
+    pub fn try_get_typed<T: 'static>(&self, id: &Tid<T, S, U>) -> Option<&T> {
+        self.try_get(id.id())
+    }
+
     pub fn try_get<T: 'static>(&self, id: &Id<S, U>) -> Option<&T> {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                let location = unsafe { *token.location() };
-                match self.buckets.get(location.bucket_index().into()) {
-                    Some((type_id, bucket)) => {
-                        if TypeId::of::<T>() != *type_id {
-                            return None;
-                        }
-
-                        bucket.try_get(location.inbucket_index())
-                    }
-                    None => None,
-                }
+        let location = self.live_location(id)?;
+        match &self.buckets[location.bucket_index().into()] {
+            (type_id, bucket) if *type_id == TypeId::of::<T>() => {
+                bucket.try_get(location.inbucket_index())
             }
-            None => None,
+            _ => None,
         }
     }
 
+    #[track_caller]
     pub fn get_mut<T: 'static>(&mut self, id: &Id<S, U>) -> &mut T {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                let location = unsafe { *token.location() };
-                let (type_id, bucket) = &mut self.buckets[location.bucket_index().into()];
-                if TypeId::of::<T>() != *type_id {
-                    panic!();
+        match self.live_location(id) {
+            Some(location) => {
+                if TypeId::of::<T>() != self.buckets[location.bucket_index().into()].0 {
+                    self.get_failed::<T>(id);
                 }
+                let bucket = &mut self.buckets[location.bucket_index().into()].1;
+
+                #[cfg(feature = "dirty-tracking")]
+                self.dirty.entry(TypeId::of::<T>()).or_default().insert(*id);
 
                 unsafe { bucket.get_mut_unchecked(location.inbucket_index()) }
             }
-            None => panic!(),
+            None => self.get_failed::<T>(id),
         }
     }
 
-    pub fn try_get_mut<T: 'static>(&mut self, id: &Id<S, U>) -> Option<&mut T> {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                let location = unsafe { *token.location() };
-                match self.buckets.get_mut(location.bucket_index().into()) {
-                    Some((type_id, bucket)) => {
-                        if TypeId::of::<T>() != *type_id {
-                            return None;
-                        }
-
-                        bucket.try_get_mut(location.inbucket_index())
-                    }
-                    None => None,
-                }
-            }
-            None => None,
+    // A and B have to be different types, values of different types never alias
+    #[track_caller]
+    pub fn get2_mut<A: 'static, B: 'static>(
+        &mut self,
+        a: &Id<S, U>,
+        b: &Id<S, U>,
+    ) -> (&mut A, &mut B) {
+        assert_ne!(
+            TypeId::of::<A>(),
+            TypeId::of::<B>(),
+            "get2_mut needs two different types"
+        );
+        if !self.contains_exact::<A>(a) {
+            self.get_failed::<A>(a);
+        }
+        if !self.contains_exact::<B>(b) {
+            self.get_failed::<B>(b);
         }
-    }
 
-    pub fn contains(&self, id: &Id<S, U>) -> bool {
-        self.tokens.contains(id.token_index(), id.tag())
+        self.try_get2_mut(a, b).unwrap()
     }
 
-    pub fn contains_exact<T: 'static>(&self, id: &Id<S, U>) -> bool {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                let usize_bucket_index = unsafe { token.location().bucket_index().into() };
-                if usize_bucket_index >= self.buckets.len() {
-                    return false;
-                }
+    // None also when A and B are the same type
+    pub fn try_get2_mut<A: 'static, B: 'static>(
+        &mut self,
+        a: &Id<S, U>,
+        b: &Id<S, U>,
+    ) -> Option<(&mut A, &mut B)> {
+        if TypeId::of::<A>() == TypeId::of::<B>() {
+            return None;
+        }
+        let a_location = self.live_location(a)?;
+        let b_location = self.live_location(b)?;
+        let a_bucket = &self.buckets[a_location.bucket_index().into()];
+        let b_bucket = &self.buckets[b_location.bucket_index().into()];
+        if a_bucket.0 != TypeId::of::<A>() || b_bucket.0 != TypeId::of::<B>() {
+            return None;
+        }
 
-                let (type_id, _) = &self.buckets[usize_bucket_index];
-                if TypeId::of::<T>() != *type_id {
-                    return false;
-                }
+        let pair = unsafe {
+            (
+                &mut *a_bucket
+                    .1
+                    .any_ptr_unchecked(a_location.inbucket_index().into())
+                    .cast::<A>(),
+                &mut *b_bucket
+                    .1
+                    .any_ptr_unchecked(b_location.inbucket_index().into())
+                    .cast::<B>(),
+            )
+        };
 
-                let tag = token.tag();
-                id.tag() == token.tag() && !tag.is_removed() && !tag.is_locked()
-            }
-            None => false,
+        #[cfg(feature = "dirty-tracking")]
+        {
+            self.dirty.entry(TypeId::of::<A>()).or_default().insert(*a);
+            self.dirty.entry(TypeId::of::<B>()).or_default().insert(*b);
         }
+
+        Some(pair)
     }
 
-    pub fn shrink_to_fit(&mut self) {
-        self.tokens.shrink_to_fit();
-        self.bucket_indexes.shrink_to_fit();
-        for (_, bucket) in self.buckets.iter_mut() {
-            unsafe {
-                bucket.shrink_to_fit();
-            }
+    // None for ids that do not resolve, panics if two ids resolve to the same value
+    pub fn get_disjoint_mut<const N: usize>(
+        &mut self,
+        ids: [&Id<S, U>; N],
+    ) -> [Option<&mut dyn Any>; N] {
+        let locations = ids.map(|id| self.live_location(id));
+        for (index, location) in locations.iter().enumerate() {
+            let Some(location) = location else {
+                continue;
+            };
+            let aliased = locations[..index].iter().flatten().any(|other| {
+                other.bucket_index() == location.bucket_index()
+                    && other.inbucket_index() == location.inbucket_index()
+            });
+            assert!(!aliased, "ids resolve to the same value");
         }
-        self.buckets.shrink_to_fit();
-    }
 
-    // // remove all placed data
-    pub fn clear(&mut self) {
-        self.tokens.clear();
-        for (_, bucket) in self.buckets.iter_mut() {
-            unsafe {
-                Bucket::clear(bucket);
+        #[cfg(feature = "dirty-tracking")]
+        for (id, location) in ids.iter().zip(locations.iter()) {
+            if let Some(location) = location {
+                let type_id = self.buckets[location.bucket_index().into()].0;
+                self.dirty.entry(type_id).or_default().insert(**id);
             }
         }
+
+        // the locations are distinct, so the references never overlap
+        let buckets = &self.buckets;
+        locations.map(|location| {
+            location.map(|location| unsafe {
+                &mut *buckets[location.bucket_index().into()]
+                    .1
+                    .any_ptr_unchecked(location.inbucket_index().into())
+            })
+        })
     }
 
-    // remove all placed data, reset all tags, reset all locked cells
-    pub fn reset(&mut self) {
-        self.tokens.reset_tokens();
-        self.bucket_indexes.clear();
-        for (_, bucket) in self.buckets.iter_mut() {
-            unsafe {
-                Bucket::clear(bucket);
+    pub fn try_get_mut<T: 'static>(&mut self, id: &Id<S, U>) -> Option<&mut T> {
+        let location = self.live_location(id)?;
+        match &mut self.buckets[location.bucket_index().into()] {
+            (type_id, bucket) if *type_id == TypeId::of::<T>() => {
+                let data = bucket.try_get_mut(location.inbucket_index());
+                #[cfg(feature = "dirty-tracking")]
+                if data.is_some() {
+                    self.dirty.entry(*type_id).or_default().insert(*id);
+                }
+
+                data
             }
+            _ => None,
         }
     }
 
-    pub fn bucket_ref<T: 'static>(&mut self) -> BucketRef<'_, S, U> {
-        BucketRef {
-            tokens: &mut self.tokens,
-            data: &mut self.buckets,
-            entry: self.bucket_indexes.entry(TypeId::of::<T>()),
-        }
+    // ids handed out by get_mut/try_get_mut since the last call, i.e. potentially mutated
+    #[cfg(feature = "dirty-tracking")]
+    pub fn take_dirty<T: 'static>(&mut self) -> Vec<Id<S, U>> {
+        self.dirty
+            .remove(&TypeId::of::<T>())
+            .map_or(vec![], |dirty| dirty.into_iter().collect())
     }
 
-    pub fn get_type_id(&self, id: &Id<S, U>) -> Option<&TypeId> {
-        match self.tokens.try_get_token(id.token_index()) {
-            Some(token) => {
-                let location = unsafe { *token.location() };
-                let (type_id, _) = &self.buckets[location.bucket_index().into()];
-                Some(type_id)
-            }
-            None => None,
-        }
+    // live values of all types, equal to the sum of the bucket lengths, neither
+    // reserved capacity nor removed or locked tokens count
+    pub fn len(&self) -> usize {
+        self.len
     }
-}
 
-impl Default for Storage {
-    fn default() -> Self {
-        Self::new()
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
-}
 
-impl<S: Size, U: UniqueTag> Drop for Storage<S, U> {
-    fn drop(&mut self) {
-        for (_, bucket) in self.buckets.iter_mut() {
-            unsafe { Bucket::drop(bucket) }
-        }
+    // changes on every place/remove of a T, starts over from 0 after reset
+    pub fn type_version<T: 'static>(&self) -> u64 {
+        self.bucket_of::<T>().map_or(0, |bucket| bucket.version())
     }
-}
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
-pub struct Id<S: Size, U: UniqueTag> {
-    token_index: S,
-    tag: U,
-}
+    // live values of T, 0 for types never placed
+    pub fn count<T: 'static>(&self) -> usize {
+        self.bucket_of::<T>().map_or(0, |bucket| bucket.len())
+    }
 
-impl<S: Size, U: UniqueTag> Id<S, U> {
-    pub(crate) fn new(token_index: S, tag: U) -> Self {
-        Self { tag, token_index }
+    pub fn capacity<T: 'static>(&self) -> usize {
+        self.bucket_of::<T>().map_or(0, |bucket| bucket.capacity())
     }
 
-    pub(crate) fn token_index(&self) -> S {
-        self.token_index
+    // the bucket_indexes part is approximate, the map overhead is not counted
+    pub fn total_allocated_bytes(&self) -> usize {
+        let buckets = self.buckets.capacity() * size_of::<(TypeId, Bucket<S>)>()
+            + self
+                .buckets
+                .iter()
+                .map(|(_, bucket)| bucket.allocated_bytes())
+                .sum::<usize>();
+        let bucket_indexes = self.bucket_indexes.capacity() * size_of::<(TypeId, S)>();
+
+        self.tokens.allocated_bytes() + buckets + bucket_indexes
     }
 
-    pub(crate) fn tag(&self) -> U {
-        self.tag
+    // (payload bytes, bytes added per value by the token index and padding)
+    pub const fn cell_overhead<T: 'static>() -> (usize, usize) {
+        let cell = cell_layout::<T, S>().size();
+        (size_of::<T>(), cell - size_of::<T>())
     }
-}
 
-pub struct BucketRef<'a, S: Size, U: UniqueTag> {
-    tokens: &'a mut TokenBucket<S, U>,
-    data: &'a mut Vec<(TypeId, Bucket<S>)>,
-    entry: std::collections::hash_map::Entry<'a, TypeId, S>,
-}
+    pub fn iteration_bytes<T: 'static>(&self) -> usize {
+        self.bucket_of::<T>()
+            .map_or(0, |bucket| bucket.iteration_bytes())
+    }
 
-impl<'a, S: Size, U: UniqueTag> BucketRef<'a, S, U> {
-    pub fn bucket_is_exists(&self) -> bool {
-        match self.entry {
-            std::collections::hash_map::Entry::Occupied(_) => true,
-            std::collections::hash_map::Entry::Vacant(_) => false,
-        }
+    pub fn count_where<T: 'static, F: Fn(&T) -> bool>(&self, pred: F) -> usize {
+        self.bucket_of::<T>().map_or(0, |bucket| {
+            unsafe { bucket.iter_unchecked::<T>() }
+                .filter(|data| pred(data))
+                .count()
+        })
     }
-}
 
-mod tests {
-    #[test]
-    fn place_remove_contains() {
-        use super::*;
+    // (token index, current tag) of every token. Removed tokens keep the removed bit,
+    // locked ones are never reissued and are left out
+    pub fn generation_snapshot(&self) -> Vec<(usize, u128)> {
+        self.tokens
+            .tags()
+            .enumerate()
+            .filter(|(_, tag)| !tag.is_locked())
+            .map(|(token_index, tag)| (token_index, tag.current() as u128))
+            .collect()
+    }
 
-        type Color = (String, u8, u8, u8);
+    // removed token slots waiting on the free list, locked ones are not counted
+    pub fn dangling_tokens(&self) -> impl Iterator<Item = usize> + '_ {
+        self.tokens
+            .tags()
+            .enumerate()
+            .filter(|(_, tag)| tag.is_removed() && !tag.is_locked())
+            .map(|(token_index, _)| token_index)
+    }
 
-        let mut storage = Storage::new();
-        let red = storage.place::<Color>((String::from("red"), 255, 0, 0));
-        let green = storage.place::<Color>((String::from("green"), 0, 255, 0));
+    pub fn dangling_token_count(&self) -> usize {
+        self.dangling_tokens().count()
+    }
 
-        let mut ids = vec![];
-        for _ in 0..10_000 {
-            ids.push(storage.place::<Color>((String::from("blue"), 255, 0, 0)));
-        }
+    // true for stale ids too, false for ids this storage never handed out a slot for
+    pub fn was_ever_issued(&self, id: &Id<S, U>) -> bool {
+        id.token_index().into() < self.tokens.len()
+    }
 
-        for id in ids.iter() {
-            storage.remove::<Color>(id);
+    // appends 0..len and returns len, values of T are contiguous in bucket order, id_at
+    // maps an index back to its id until the next place or remove of T. None and
+    // nothing appended if len does not fit u32
+    pub fn pack_indices<T: 'static>(&self, out: &mut Vec<u32>) -> Option<usize> {
+        let len = self.bucket_of::<T>().map_or(0, |bucket| bucket.len());
+        out.extend(0..u32::try_from(len).ok()?);
+        Some(len)
+    }
+
+    pub fn id_at<T: 'static>(&self, inbucket_index: usize) -> Option<Id<S, U>> {
+        let bucket = self.bucket_of::<T>()?;
+        if inbucket_index >= bucket.len() {
+            return None;
         }
 
-        assert!(storage.contains(&red));
-        assert!(storage.contains(&green));
+        Some(unsafe { self.id_at_unchecked(bucket, inbucket_index) })
+    }
 
-        storage.remove::<Color>(&red);
-        assert!(!storage.contains(&red));
-        assert!(storage.contains(&green));
+    // the id stored right after this one in its bucket, removals reorder the bucket
+    pub fn neighbor(&self, id: &Id<S, U>) -> Option<Id<S, U>> {
+        self.neighbor_at(id, true)
+    }
 
-        storage.remove::<Color>(&green);
-        assert!(!storage.contains(&red));
-        assert!(!storage.contains(&green));
+    fn neighbor_at(&self, id: &Id<S, U>, next: bool) -> Option<Id<S, U>> {
+        let location = self.live_location(id)?;
+        let bucket = &self.buckets[location.bucket_index().into()].1;
+        let inbucket_index = location.inbucket_index().into();
+        let inbucket_index = if next {
+            inbucket_index + 1
+        } else {
+            inbucket_index.checked_sub(1)?
+        };
+        if inbucket_index >= bucket.len() {
+            return None;
+        }
+
+        Some(unsafe { self.id_at_unchecked(bucket, inbucket_index) })
     }
 
-    #[test]
+    // f also gets the values next to the id in its bucket, they are looked up on demand
+    pub fn with_context<T: 'static, R>(
+        &self,
+        id: &Id<S, U>,
+        f: impl FnOnce(&T, Neighbors<'_, T, S, U>) -> R,
+    ) -> Option<R> {
+        let data = self.try_get::<T>(id)?;
+        Some(f(
+            data,
+            Neighbors {
+                storage: self,
+                id: *id,
+                phantom: PhantomData,
+            },
+        ))
+    }
+
+    pub fn contains(&self, id: &Id<S, U>) -> bool {
+        self.tokens.contains(id.token_index(), id.tag())
+    }
+
+    // out must be as long as ids
+    pub fn contains_batch(&self, ids: &[Id<S, U>], out: &mut [bool]) {
+        assert_eq!(ids.len(), out.len());
+        for (id, contains) in ids.iter().zip(out.iter_mut()) {
+            *contains = self.tokens.contains(id.token_index(), id.tag());
+        }
+    }
+
+    pub fn partition_live(&self, ids: &[Id<S, U>]) -> LiveAndStale<S, U> {
+        ids.iter().partition(|id| self.contains(id))
+    }
+
+    // false for stale ids, the location of a removed token is never read
+    pub fn is_type<T: 'static>(&self, id: &Id<S, U>) -> bool {
+        self.get_type_id(id) == Some(&TypeId::of::<T>())
+    }
+
+    pub fn contains_typed<T: 'static>(&self, id: &Tid<T, S, U>) -> bool {
+        self.contains(id.id())
+    }
+
+    pub fn contains_exact<T: 'static>(&self, id: &Id<S, U>) -> bool {
+        // removed and locked tokens hold a free list index instead of a location
+        match self.live_location(id) {
+            Some(location) => match self.buckets.get(location.bucket_index().into()) {
+                Some((type_id, _)) => TypeId::of::<T>() == *type_id,
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.tokens.shrink_to_fit();
+        self.bucket_indexes.shrink_to_fit();
+        for (_, bucket) in self.buckets.iter_mut() {
+            unsafe {
+                bucket.shrink_to_fit();
+            }
+        }
+        self.buckets.shrink_to_fit();
+    }
+
+    // shrinks only the buckets with more than max_waste_ratio of unused capacity
+    pub fn trim(&mut self, max_waste_ratio: f32) {
+        for (_, bucket) in self.buckets.iter_mut() {
+            if bucket.fragmentation() > max_waste_ratio as f64 {
+                unsafe { bucket.shrink_to_fit() }
+            }
+        }
+    }
+
+    // // remove all placed data
+    pub fn clear(&mut self) {
+        self.tokens.clear();
+        self.clear_values();
+    }
+
+    // remove all placed data, reset all tags, reset all locked cells
+    pub fn reset(&mut self) {
+        self.tokens.reset_tokens();
+        self.bucket_indexes.clear();
+        self.hot_types = [None; HOT_TYPES];
+        self.clear_values();
+    }
+
+    // the part of clear and reset past the tokens
+    fn clear_values(&mut self) {
+        for eviction in self.evictions.values_mut() {
+            eviction.order.clear();
+        }
+        #[cfg(feature = "track-removals")]
+        self.removals.clear();
+        #[cfg(feature = "dirty-tracking")]
+        self.dirty.clear();
+        if let Some(discriminants) = self.discriminants.as_mut() {
+            discriminants.clear();
+        }
+        self.len = 0;
+        self.clear_buckets();
+    }
+
+    fn clear_buckets(&mut self) {
+        self.set_tearing_down(true);
+        self.for_each_bucket_unwind_safe(|bucket| unsafe { Bucket::clear(bucket) });
+        self.set_tearing_down(false);
+    }
+
+    // a panicking drop in one bucket does not stop the others from being visited,
+    // the first panic is resumed afterwards
+    fn for_each_bucket_unwind_safe(&mut self, mut f: impl FnMut(&mut Bucket<S>)) {
+        let mut panic = None;
+        for (_, bucket) in self.buckets.iter_mut() {
+            if let Err(payload) = catch_unwind(AssertUnwindSafe(|| f(bucket))) {
+                panic.get_or_insert(payload);
+            }
+        }
+
+        if let Some(payload) = panic {
+            self.set_tearing_down(false);
+            resume_unwind(payload);
+        }
+    }
+
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn set_tearing_down(&mut self, tearing_down: bool) {
+        #[cfg(debug_assertions)]
+        {
+            self.tearing_down = tearing_down;
+        }
+    }
+
+    fn assert_not_tearing_down(&self) {
+        #[cfg(debug_assertions)]
+        if self.tearing_down {
+            panic!("place called from a drop while the storage is cleared or dropped");
+        }
+    }
+
+    /// # Safety
+    ///
+    /// Every placed `Old` value must be a valid `New` value, e.g. both are
+    /// transparent wrappers around the same type.
+    pub unsafe fn rename_type<Old: 'static, New: 'static>(&mut self) {
+        assert_eq!(Layout::new::<Old>(), Layout::new::<New>());
+        assert!(!self.bucket_indexes.contains_key(&TypeId::of::<New>()));
+
+        if let Some(bucket_index) = self.bucket_indexes.remove(&TypeId::of::<Old>()) {
+            for hot in self.hot_types.iter_mut() {
+                if matches!(hot, Some((type_id, _)) if *type_id == TypeId::of::<Old>()) {
+                    *hot = None;
+                }
+            }
+
+            self.bucket_indexes
+                .insert(TypeId::of::<New>(), bucket_index);
+
+            let (type_id, bucket) = &mut self.buckets[bucket_index.into()];
+            *type_id = TypeId::of::<New>();
+            unsafe { bucket.retype::<New>() };
+        }
+    }
+
+    pub fn into_remapped(mut self) -> Remapped<S, U> {
+        let mut values = vec![];
+        let mut remap = vec![];
+        for (type_id, bucket) in self.buckets.iter_mut() {
+            bucket.drain_any(|token_index, value| {
+                let tag = unsafe { self.tokens.get_token_unchecked(token_index).tag() };
+                remap.push((Id::new(token_index, tag), values.len()));
+                values.push((*type_id, value));
+            });
+        }
+
+        (values, remap)
+    }
+
+    // moves every T into dest, handing over the whole allocation when dest has no
+    // bucket of T yet, on_place hooks of dest only run on the element by element path
+    pub fn donate_bucket<T: 'static>(&mut self, dest: &mut Storage<S, U>) -> IdRemap<S, U> {
+        let mut remap = IdRemap::new();
+        let bucket_index = match self.bucket_index_of::<T>() {
+            Some(bucket_index) => bucket_index,
+            None => return remap,
+        };
+
+        if dest.bucket_index_of::<T>().is_some() {
+            while self.buckets[bucket_index.into()].1.len() != 0 {
+                let inbucket_index = self.buckets[bucket_index.into()].1.len() - 1;
+                let id = unsafe {
+                    self.id_at_unchecked(&self.buckets[bucket_index.into()].1, inbucket_index)
+                };
+                let data =
+                    unsafe { self.remove_at_unchecked::<T>(bucket_index, inbucket_index.into()) };
+                remap.insert(id, dest.place(data));
+            }
+            self.hand_over_dirty::<T>(dest, &remap);
+            return remap;
+        }
+
+        let mut empty = Bucket::new::<T>(self.bucket_capacity);
+        empty.set_growth_factor(self.growth_factor);
+        empty.set_version(self.type_version::<T>() + 1);
+        let mut bucket = core::mem::replace(&mut self.buckets[bucket_index.into()].1, empty);
+        bucket.set_growth_factor(dest.growth_factor);
+
+        let dest_bucket_index = dest.bucket_index_or_insert::<T>();
+        let mut placed = Vec::with_capacity(bucket.len());
+        for inbucket_index in 0..bucket.len() {
+            let old = unsafe { self.id_at_unchecked(&bucket, inbucket_index) };
+            let discriminant = self.discriminant(&old).unwrap_or(0);
+            self.tokens.mark_removed(old.token_index());
+            self.len -= 1;
+
+            let (token_index, tag) = dest.tokens.create(dest_bucket_index, inbucket_index.into());
+            #[cfg(feature = "track-removals")]
+            dest.removals.remove(&token_index);
+            unsafe { bucket.set_token_index_unchecked::<T>(inbucket_index.into(), token_index) };
+            set_discriminant(&mut dest.discriminants, token_index.into(), discriminant);
+            dest.len += 1;
+            remap.insert(old, Id::new(token_index, tag));
+            placed.push(Id::new(token_index, tag));
+        }
+
+        let dest_bucket = &mut dest.buckets[dest_bucket_index.into()].1;
+        bucket.set_version(bucket.version() + 1);
+        unsafe { Bucket::drop(dest_bucket) };
+        *dest_bucket = bucket;
+
+        for id in placed {
+            dest.queue_for_eviction::<T>(id);
+        }
+        self.hand_over_dirty::<T>(dest, &remap);
+        remap
+    }
+
+    // modified values of T not taken yet stay modified under their ids in dest
+    #[cfg_attr(not(feature = "dirty-tracking"), allow(unused_variables))]
+    fn hand_over_dirty<T: 'static>(&mut self, dest: &mut Storage<S, U>, remap: &IdRemap<S, U>) {
+        #[cfg(feature = "dirty-tracking")]
+        if let Some(dirty) = self.dirty.remove(&TypeId::of::<T>()) {
+            dest.dirty
+                .entry(TypeId::of::<T>())
+                .or_default()
+                .extend(dirty.into_iter().filter_map(|id| remap.get(id)));
+        }
+    }
+
+    // moves every T out and releases the bucket's allocation, all ids of T go stale
+    pub fn take_bucket<T: 'static>(&mut self) -> Vec<T> {
+        let bucket_index = match self.bucket_index_of::<T>() {
+            Some(bucket_index) => bucket_index,
+            None => return vec![],
+        };
+
+        let mut empty = Bucket::new::<T>(self.bucket_capacity);
+        empty.set_growth_factor(self.growth_factor);
+        let mut bucket = core::mem::replace(&mut self.buckets[bucket_index.into()].1, empty);
+
+        let mut values = Vec::with_capacity(bucket.len());
+        unsafe {
+            bucket.drain_unchecked::<T>(|token_index, value| {
+                self.tokens.mark_removed(token_index);
+                values.push(value);
+            })
+        };
+        self.len -= values.len();
+
+        self.buckets[bucket_index.into()]
+            .1
+            .set_version(bucket.version());
+        unsafe { Bucket::drop(&mut bucket) };
+        values
+    }
+
+    // rebuilds the storage with another Size, place hooks, eviction caps and hot
+    // types are not carried over
+    pub fn resize_to<NewS: Size>(mut self) -> (Storage<NewS, U>, IdRemap<S, U, NewS>) {
+        assert!(self.len <= NewS::max() && self.buckets.len() <= NewS::max());
+
+        let mut storage = Storage::new_with_tag_and_size::<NewS, U>();
+        storage.bucket_capacity = self.bucket_capacity;
+        storage.growth_factor = self.growth_factor;
+        storage.len = self.len;
+        storage.tokens.reserve_exact(self.len);
+        if self.discriminants.is_some() {
+            storage.discriminants = Some(Vec::with_capacity(self.len));
+        }
+
+        let mut remap = IdRemap::new();
+        for (type_id, bucket) in self.buckets.iter_mut() {
+            let bucket_index = storage.buckets.len();
+            let mut inbucket_index = 0;
+            let migrated = bucket.migrate::<NewS>(&mut |token_index| {
+                let old = Id::new(token_index, unsafe {
+                    self.tokens.get_token_unchecked(token_index).tag()
+                });
+                let (token_index, tag) = storage
+                    .tokens
+                    .create(bucket_index.into(), inbucket_index.into());
+                inbucket_index += 1;
+
+                let discriminant = self
+                    .discriminants
+                    .as_ref()
+                    .map_or(0, |discriminants| discriminants[old.token_index().into()]);
+                set_discriminant(&mut storage.discriminants, token_index.into(), discriminant);
+                remap.insert(old, Id::new(token_index, tag));
+                token_index.into()
+            });
+
+            storage.buckets.push((*type_id, migrated));
+            storage.bucket_indexes.insert(*type_id, bucket_index.into());
+        }
+
+        self.len = 0;
+        (storage, remap)
+    }
+
+    // renumbers live tokens to 0..len in bucket order and drops the free list, every
+    // outstanding id is invalidated, new tags are past the old ones so none of them
+    // resolves again
+    pub fn pack_tokens(&mut self) -> IdRemap<S, U> {
+        let mut locations = Vec::with_capacity(self.len);
+        let mut old_ids = Vec::with_capacity(self.len);
+        for (bucket_index, (_, bucket)) in self.buckets.iter().enumerate() {
+            for inbucket_index in 0..bucket.len() {
+                locations.push((bucket_index.into(), inbucket_index.into()));
+                old_ids.push(unsafe { self.id_at_unchecked(bucket, inbucket_index) });
+            }
+        }
+        let packed = self.tokens.pack(&locations);
+
+        let mut discriminants = self
+            .discriminants
+            .as_ref()
+            .map(|_| Vec::with_capacity(self.tokens.len()));
+        let mut remap = IdRemap::new();
+        for ((bucket_index, inbucket_index), (old, (token_index, tag))) in
+            locations.into_iter().zip(old_ids.into_iter().zip(packed))
+        {
+            let bucket = &mut self.buckets[bucket_index.into()].1;
+            unsafe { bucket.set_token_index_at_unchecked(inbucket_index.into(), token_index) };
+            if let Some(old_discriminants) = self.discriminants.as_ref() {
+                let discriminant = old_discriminants[old.token_index().into()];
+                set_discriminant(&mut discriminants, token_index.into(), discriminant);
+            }
+            remap.insert(old, Id::new(token_index, tag));
+        }
+        self.discriminants = discriminants;
+
+        for eviction in self.evictions.values_mut() {
+            eviction.order = eviction
+                .order
+                .iter()
+                .filter_map(|id| remap.get(*id))
+                .collect();
+        }
+        #[cfg(feature = "dirty-tracking")]
+        for dirty in self.dirty.values_mut() {
+            *dirty = dirty.iter().filter_map(|id| remap.get(*id)).collect();
+        }
+        #[cfg(feature = "track-removals")]
+        self.removals.clear();
+
+        remap
+    }
+
+    pub fn report(&self) -> StorageReport {
+        StorageReport::new(self)
+    }
+
+    pub fn apply(&mut self, buffer: CommandBuffer<S, U>) -> Vec<Id<S, U>> {
+        buffer.apply(self)
+    }
+
+    // same as &*storage, for handing a storage to read-only code
+    pub fn freeze(&mut self) -> &Self {
+        self
+    }
+
+    pub fn snapshot(&self) -> Snapshot<'_, S, U> {
+        Snapshot::new(self)
+    }
+
+    pub fn bucket_ref<T: 'static>(&mut self) -> BucketRef<'_, T, S, U> {
+        BucketRef {
+            bucket_index: self.bucket_index_of::<T>(),
+            storage: self,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn all_ids(&self) -> impl Iterator<Item = (TypeId, Id<S, U>)> + '_ {
+        self.buckets.iter().flat_map(move |(type_id, bucket)| {
+            (0..bucket.len()).map(move |inbucket_index| {
+                (*type_id, unsafe {
+                    self.id_at_unchecked(bucket, inbucket_index)
+                })
+            })
+        })
+    }
+
+    // every id passes contains, see ids
+    pub fn iter_with_ids<T: 'static>(&self) -> impl Iterator<Item = (Id<S, U>, &T)> {
+        self.cells::<T>().map(move |(token_index, data)| {
+            let tag = unsafe { self.tokens.get_token_unchecked(token_index).tag() };
+            (Id::new(token_index, tag), data)
+        })
+    }
+
+    // all values of T count as modified
+    pub fn iter_with_ids_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Id<S, U>, &mut T)> {
+        let bucket_index = self.bucket_index_of::<T>();
+        if let Some(bucket_index) = bucket_index {
+            self.mark_all_dirty(bucket_index);
+        }
+
+        let tokens = &self.tokens;
+        Self::cells_mut::<T>(&mut self.buckets, bucket_index).map(move |(token_index, data)| {
+            let tag = unsafe { tokens.get_token_unchecked(token_index).tag() };
+            (Id::new(token_index, tag), data)
+        })
+    }
+
+    // cells only ever belong to live tokens so every id passes contains
+    pub fn ids<T: 'static>(&self) -> impl Iterator<Item = Id<S, U>> + '_ {
+        self.iter_with_ids::<T>().map(|(id, _)| id)
+    }
+
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = &T> {
+        self.cells::<T>().map(|(_, data)| data)
+    }
+
+    // all values of T count as modified
+    pub fn iter_mut<T: 'static>(&mut self) -> impl Iterator<Item = &mut T> {
+        let bucket_index = self.bucket_index_of::<T>();
+        if let Some(bucket_index) = bucket_index {
+            self.mark_all_dirty(bucket_index);
+        }
+
+        Self::cells_mut::<T>(&mut self.buckets, bucket_index).map(|(_, data)| data)
+    }
+
+    pub fn iter_typed<T: 'static>(&self) -> impl Iterator<Item = (Tid<T, S, U>, &T)> {
+        self.iter_with_ids::<T>()
+            .map(|(id, data)| (Tid::new(id), data))
+    }
+
+    // bucket order, with the deterministic feature values come in token index order
+    // instead of the swap remove order, at the cost of collecting and sorting them first
+    fn cells<T: 'static>(&self) -> impl Iterator<Item = (S, &T)> {
+        let iter = self
+            .bucket_of::<T>()
+            .into_iter()
+            .flat_map(|bucket| unsafe { bucket.iter_with_token_indices_unchecked::<T>(0) });
+        #[cfg(feature = "deterministic")]
+        let iter = by_token_index(iter);
+        iter
+    }
+
+    // mutable counterpart of cells, takes the buckets alone so the tokens stay readable
+    fn cells_mut<T: 'static>(
+        buckets: &mut [(TypeId, Bucket<S>)],
+        bucket_index: Option<S>,
+    ) -> impl Iterator<Item = (S, &mut T)> {
+        let iter = bucket_index
+            .map(|bucket_index| &mut buckets[bucket_index.into()].1)
+            .into_iter()
+            .flat_map(|bucket| unsafe { bucket.iter_mut_with_token_indices_unchecked::<T>() });
+        #[cfg(feature = "deterministic")]
+        let iter = by_token_index(iter);
+        iter
+    }
+
+    // every unordered pair of values of T once, n * (n - 1) / 2 pairs in the order of iter
+    pub fn iter_pairs<T: 'static>(&self) -> impl Iterator<Item = (&T, &T)> {
+        let values = self.iter::<T>().collect::<Vec<_>>();
+        let len = values.len();
+
+        (0..len)
+            .flat_map(move |first| (first + 1..len).map(move |second| (first, second)))
+            .map(move |(first, second)| (values[first], values[second]))
+    }
+
+    // mutable counterpart of iter_pairs, all values of T count as modified
+    pub fn for_each_pair_mut<T: 'static>(&mut self, mut f: impl FnMut(&mut T, &mut T)) {
+        let mut values = self.iter_mut::<T>().collect::<Vec<_>>();
+        for split in 1..values.len() {
+            let (head, tail) = values.split_at_mut(split);
+            let first = &mut *head[split - 1];
+            for second in tail {
+                f(first, second)
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "dirty-tracking"), allow(unused_variables))]
+    fn mark_all_dirty(&mut self, bucket_index: S) {
+        #[cfg(feature = "dirty-tracking")]
+        {
+            let (type_id, bucket) = &self.buckets[bucket_index.into()];
+            let ids = (0..bucket.len())
+                .map(|inbucket_index| unsafe { self.id_at_unchecked(bucket, inbucket_index) })
+                .collect::<Vec<_>>();
+            self.dirty.entry(*type_id).or_default().extend(ids);
+        }
+    }
+
+    // resumes at the current position of start, swap removes since may skip or repeat values
+    pub fn iter_from<T: 'static>(&self, start: &Id<S, U>) -> impl Iterator<Item = (Id<S, U>, &T)> {
+        let from = match (self.live_location(start), self.bucket_index_of::<T>()) {
+            (Some(location), Some(bucket_index)) if location.bucket_index() == bucket_index => {
+                location.inbucket_index().into()
+            }
+            _ => usize::MAX,
+        };
+
+        self.iter_with_ids_from::<T>(from)
+    }
+
+    fn iter_with_ids_from<T: 'static>(&self, from: usize) -> impl Iterator<Item = (Id<S, U>, &T)> {
+        self.bucket_of::<T>().into_iter().flat_map(move |bucket| {
+            unsafe { bucket.iter_with_token_indices_unchecked::<T>(from) }.map(
+                move |(token_index, data)| {
+                    let tag = unsafe { self.tokens.get_token_unchecked(token_index).tag() };
+                    (Id::new(token_index, tag), data)
+                },
+            )
+        })
+    }
+
+    pub fn ids_of_types<'a>(
+        &'a self,
+        pred: impl Fn(TypeId) -> bool + 'a,
+    ) -> impl Iterator<Item = (TypeId, Id<S, U>)> + 'a {
+        self.all_ids().filter(move |(type_id, _)| pred(*type_id))
+    }
+
+    // debug builds panic if the internal counters disagree with the buckets and tokens
+    #[doc(hidden)]
+    pub fn validate(&self) {
+        let bucket_len = self.buckets().map(|bucket| bucket.len()).sum::<usize>();
+        debug_assert_eq!(self.len, bucket_len);
+
+        let live_tokens = self
+            .tokens
+            .tags()
+            .filter(|tag| !tag.is_removed() && !tag.is_locked())
+            .count();
+        debug_assert_eq!(self.len, live_tokens);
+
+        // every cell has to point back at a live token that locates the same cell
+        for (bucket_index, (_, bucket)) in self.buckets.iter().enumerate() {
+            for inbucket_index in 0..bucket.len() {
+                let token_index = unsafe { bucket.token_index_unchecked(inbucket_index) };
+                let location = self
+                    .tokens
+                    .try_get_token(token_index)
+                    .and_then(|token| token.try_location());
+                debug_assert!(location.is_some_and(|location| {
+                    location.bucket_index().into() == bucket_index
+                        && location.inbucket_index().into() == inbucket_index
+                }));
+            }
+        }
+    }
+
+    pub(crate) fn buckets(&self) -> impl Iterator<Item = &Bucket<S>> {
+        self.buckets.iter().map(|(_, bucket)| bucket)
+    }
+
+    pub(crate) fn tokens(&self) -> &TokenBucket<S, U> {
+        &self.tokens
+    }
+
+    pub(crate) fn bucket_of<T: 'static>(&self) -> Option<&Bucket<S>> {
+        self.bucket_index_of::<T>()
+            .map(|bucket_index| &self.buckets[bucket_index.into()].1)
+    }
+
+    fn bucket_index_of<T: 'static>(&self) -> Option<S> {
+        let type_id = TypeId::of::<T>();
+        match self.hot_bucket_index(type_id) {
+            Some(bucket_index) => Some(bucket_index),
+            None => self.bucket_indexes.get(&type_id).copied(),
+        }
+    }
+
+    fn hot_bucket_index(&self, type_id: TypeId) -> Option<S> {
+        self.hot_types
+            .iter()
+            .flatten()
+            .find(|(hot, _)| *hot == type_id)
+            .map(|(_, bucket_index)| *bucket_index)
+    }
+
+    fn bucket_index_or_insert<T: 'static>(&mut self) -> S {
+        self.assert_not_tearing_down();
+        let type_id = TypeId::of::<T>();
+        if let Some(bucket_index) = self.hot_bucket_index(type_id) {
+            self.debug_assert_bucket_of::<T>(bucket_index);
+            return bucket_index;
+        }
+
+        let bucket_index = *self
+            .bucket_indexes
+            .entry(type_id)
+            .or_insert(self.buckets.len().into());
+
+        if bucket_index == self.buckets.len().into() {
+            assert_ne!(self.buckets.len(), S::max());
+            self.buckets
+                .push((type_id, Bucket::new::<T>(self.bucket_capacity)));
+            self.buckets[bucket_index.into()]
+                .1
+                .set_growth_factor(self.growth_factor);
+        }
+
+        self.debug_assert_bucket_of::<T>(bucket_index);
+        bucket_index
+    }
+
+    // guards the type map against two types ever sharing one bucket
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn debug_assert_bucket_of<T: 'static>(&self, bucket_index: S) {
+        #[cfg(debug_assertions)]
+        {
+            let (type_id, bucket) = &self.buckets[bucket_index.into()];
+            assert!(
+                *type_id == TypeId::of::<T>() && bucket.type_name() == core::any::type_name::<T>(),
+                "type {} resolved to the bucket of {}",
+                core::any::type_name::<T>(),
+                bucket.type_name()
+            );
+        }
+    }
+
+    // bucket_index must point to a bucket of T
+    unsafe fn place_at_unchecked<T: 'static>(&mut self, bucket_index: S, data: T) -> Id<S, U> {
+        match unsafe { self.try_place_at_unchecked(bucket_index, data) } {
+            Ok(id) => id,
+            Err(error) => panic!("{error}"),
+        }
+    }
+
+    // bucket_index must point to a bucket of T
+    unsafe fn try_place_at_unchecked<T: 'static>(
+        &mut self,
+        bucket_index: S,
+        data: T,
+    ) -> Result<Id<S, U>, PlaceError<T>> {
+        self.assert_not_tearing_down();
+        if self.tokens.is_full() {
+            return Err(PlaceError::TokensExhausted(data));
+        }
+        let bucket = &mut self.buckets[bucket_index.into()].1;
+
+        let inbucket_index = match unsafe { bucket.push_unchecked(data) } {
+            Ok(inbucket_index) => inbucket_index,
+            Err(data) => return Err(PlaceError::BucketFull(data)),
+        };
+
+        let (token_index, tag) = self.tokens.create(bucket_index, inbucket_index);
+        // the token index was reissued, removing the new id is not a double removal
+        #[cfg(feature = "track-removals")]
+        self.removals.remove(&token_index);
+        self.len = self.len.checked_add(1).unwrap();
+        unsafe {
+            bucket.set_token_index_unchecked::<T>(inbucket_index, token_index);
+        }
+        let id = Id::new(token_index, tag);
+
+        set_discriminant(&mut self.discriminants, token_index.into(), 0);
+
+        self.queue_for_eviction::<T>(id);
+
+        if !self.place_hooks.is_empty() {
+            if let Some(hook) = self.place_hooks.get_mut(&TypeId::of::<T>()) {
+                let bucket = &self.buckets[bucket_index.into()].1;
+                let data = unsafe { bucket.get_unchecked::<T>(inbucket_index) };
+                hook((data as *const T).cast(), id);
+            }
+        }
+
+        Ok(id)
+    }
+
+    fn queue_for_eviction<T: 'static>(&mut self, id: Id<S, U>) {
+        if self.evictions.is_empty() {
+            return;
+        }
+
+        let len = self.bucket_of::<T>().map_or(0, |bucket| bucket.len());
+        if let Some(eviction) = self.evictions.get_mut(&TypeId::of::<T>()) {
+            // drops removed ids once they make up half of the queue
+            if eviction.order.len() >= 2 * usize::max(eviction.cap, len) {
+                let tokens = &self.tokens;
+                eviction
+                    .order
+                    .retain(|id| tokens.contains(id.token_index(), id.tag()));
+            }
+            eviction.order.push_back(id);
+        }
+    }
+
+    fn live_location(&self, id: &Id<S, U>) -> Option<Location<S>> {
+        let token = self.tokens.try_get_token(id.token_index())?;
+        if token.tag() != id.tag() {
+            return None;
+        }
+
+        token.try_location().copied()
+    }
+
+    unsafe fn id_at_unchecked(&self, bucket: &Bucket<S>, inbucket_index: usize) -> Id<S, U> {
+        let token_index = unsafe { bucket.token_index_unchecked(inbucket_index) };
+        let tag = unsafe { self.tokens.get_token_unchecked(token_index).tag() };
+        Id::new(token_index, tag)
+    }
+
+    // bucket_index must point to a bucket of T, inbucket_index must be in bounds
+    unsafe fn remove_at_unchecked<T: 'static>(&mut self, bucket_index: S, inbucket_index: S) -> T {
+        let bucket = &mut self.buckets[bucket_index.into()].1;
+        let token_index = unsafe { bucket.token_index_unchecked(inbucket_index.into()) };
+        self.tokens.mark_removed(token_index);
+        self.len = self.len.checked_sub(1).unwrap();
+
+        let (data, token_index_for_swap) =
+            unsafe { bucket.swap_remove_unchecked::<T>(inbucket_index) };
+        if let Some(token_index) = token_index_for_swap {
+            self.tokens.set_inbucket_index(token_index, inbucket_index)
+        }
+
+        data
+    }
+
+    // inbucket_index must be in bounds
+    unsafe fn erase_at_unchecked(&mut self, bucket_index: S, inbucket_index: S) {
+        let bucket = &mut self.buckets[bucket_index.into()].1;
+        let token_index = unsafe { bucket.token_index_unchecked(inbucket_index.into()) };
+        self.tokens.mark_removed(token_index);
+        self.len = self.len.checked_sub(1).unwrap();
+
+        if let Some(token_index) = unsafe { bucket.swap_erase_unchecked(inbucket_index) } {
+            self.tokens.set_inbucket_index(token_index, inbucket_index)
+        }
+    }
+
+    pub fn debug_location(&self, id: &Id<S, U>) -> Option<(usize, usize)> {
+        self.live_location(id).map(|location| {
+            (
+                location.bucket_index().into(),
+                location.inbucket_index().into(),
+            )
+        })
+    }
+
+    // the token index stored next to the value, equals id.token_index() unless
+    // swap bookkeeping is broken
+    pub fn cell_token_index(&self, id: &Id<S, U>) -> Option<S> {
+        self.live_location(id).map(|location| unsafe {
+            self.buckets[location.bucket_index().into()]
+                .1
+                .token_index_unchecked(location.inbucket_index().into())
+        })
+    }
+
+    pub fn get_type_id(&self, id: &Id<S, U>) -> Option<&TypeId> {
+        self.live_location(id)
+            .map(|location| &self.buckets[location.bucket_index().into()].0)
+    }
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Size, U: UniqueTag> Drop for Storage<S, U> {
+    fn drop(&mut self) {
+        self.set_tearing_down(true);
+        self.for_each_bucket_unwind_safe(|bucket| unsafe { Bucket::drop(bucket) });
+    }
+}
+
+pub struct BucketRef<'a, T: 'static, S: Size, U: UniqueTag> {
+    storage: &'a mut Storage<S, U>,
+    bucket_index: Option<S>,
+    phantom: PhantomData<fn(T)>,
+}
+
+impl<'a, T: 'static, S: Size, U: UniqueTag> BucketRef<'a, T, S, U> {
+    pub fn bucket_is_exists(&self) -> bool {
+        self.bucket_index.is_some()
+    }
+
+    // the bucket is looked up once, on the first place if it did not exist yet
+    pub fn place(&mut self, data: T) -> Id<S, U> {
+        let bucket_index = match self.bucket_index {
+            Some(bucket_index) => bucket_index,
+            None => *self
+                .bucket_index
+                .insert(self.storage.bucket_index_or_insert::<T>()),
+        };
+        unsafe { self.storage.place_at_unchecked(bucket_index, data) }
+    }
+}
+
+pub struct Neighbors<'a, T: 'static, S: Size, U: UniqueTag> {
+    storage: &'a Storage<S, U>,
+    id: Id<S, U>,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: 'static, S: Size, U: UniqueTag> Neighbors<'a, T, S, U> {
+    pub fn id(&self) -> Id<S, U> {
+        self.id
+    }
+
+    pub fn previous(&self) -> Option<(Id<S, U>, &'a T)> {
+        self.get(false)
+    }
+
+    pub fn next(&self) -> Option<(Id<S, U>, &'a T)> {
+        self.get(true)
+    }
+
+    fn get(&self, next: bool) -> Option<(Id<S, U>, &'a T)> {
+        let storage = self.storage;
+        let id = storage.neighbor_at(&self.id, next)?;
+        storage.try_get(&id).map(|data| (id, data))
+    }
+}
+
+// places values of one type without looking up its bucket on every call
+pub struct Placer<'a, T: 'static, S: Size, U: UniqueTag> {
+    storage: &'a mut Storage<S, U>,
+    bucket_index: S,
+    phantom: PhantomData<fn(T)>,
+}
+
+impl<'a, T: 'static, S: Size, U: UniqueTag> Placer<'a, T, S, U> {
+    pub fn place(&mut self, data: T) -> Id<S, U> {
+        unsafe { self.storage.place_at_unchecked(self.bucket_index, data) }
+    }
+
+    // reserves once for the whole batch, ids are in the order of values
+    pub fn place_slice(&mut self, values: Vec<T>) -> Vec<Id<S, U>> {
+        self.storage.buckets[self.bucket_index.into()]
+            .1
+            .reserve(values.len());
+        self.storage.tokens.reserve(values.len());
+
+        values.into_iter().map(|data| self.place(data)).collect()
+    }
+}
+
+pub struct BumpScope<'a, T: 'static, S: Size, U: UniqueTag> {
+    storage: &'a mut Storage<S, U>,
+    bucket_index: S,
+    start_len: usize,
+    count: usize,
+    phantom: PhantomData<fn(T)>,
+}
+
+impl<'a, T: 'static, S: Size, U: UniqueTag> BumpScope<'a, T, S, U> {
+    // at most count values, the reserved ones
+    pub fn place(&mut self, data: T) -> Id<S, U> {
+        assert!(self.len() < self.count);
+        unsafe { self.storage.place_at_unchecked(self.bucket_index, data) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.storage.buckets[self.bucket_index.into()].1.len() - self.start_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn storage(&self) -> &Storage<S, U> {
+        self.storage
+    }
+}
+
+impl<'a, T: 'static, S: Size, U: UniqueTag> Drop for BumpScope<'a, T, S, U> {
+    fn drop(&mut self) {
+        // from the end, so nothing is swapped
+        let len = self.storage.buckets[self.bucket_index.into()].1.len();
+        for inbucket_index in (self.start_len..len).rev() {
+            unsafe {
+                self.storage
+                    .erase_at_unchecked(self.bucket_index, inbucket_index.into())
+            }
+        }
+    }
+}
+
+pub struct RemovalBatch<'a, S: Size, U: UniqueTag> {
+    storage: &'a mut Storage<S, U>,
+    ids: Vec<Id<S, U>>,
+}
+
+impl<'a, S: Size, U: UniqueTag> RemovalBatch<'a, S, U> {
+    pub fn queue(&mut self, id: &Id<S, U>) {
+        self.ids.push(*id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl<'a, S: Size, U: UniqueTag> Drop for RemovalBatch<'a, S, U> {
+    fn drop(&mut self) {
+        let mut locations = self
+            .ids
+            .iter()
+            .filter_map(|id| self.storage.live_location(id))
+            .map(|location| {
+                (
+                    location.bucket_index().into(),
+                    location.inbucket_index().into(),
+                )
+            })
+            .collect::<Vec<(usize, usize)>>();
+        locations.sort_unstable_by(|a, b| b.cmp(a));
+        locations.dedup();
+
+        // descending in every bucket, so the value swapped into a hole is never a queued one
+        for (bucket_index, inbucket_index) in locations {
+            unsafe {
+                self.storage
+                    .erase_at_unchecked(bucket_index.into(), inbucket_index.into())
+            }
+        }
+    }
+}
+
+pub struct DrainFilter<'a, T: 'static, F: FnMut(&mut T) -> bool, S: Size, U: UniqueTag> {
+    storage: &'a mut Storage<S, U>,
+    bucket_index: Option<S>,
+    inbucket_index: usize,
+    pred: F,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: 'static, F: FnMut(&mut T) -> bool, S: Size, U: UniqueTag> Iterator
+    for DrainFilter<'a, T, F, S, U>
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let bucket_index = self.bucket_index?;
+        loop {
+            let bucket = &mut self.storage.buckets[bucket_index.into()].1;
+            if self.inbucket_index >= bucket.len() {
+                return None;
+            }
+
+            let data = unsafe { bucket.get_mut_unchecked(self.inbucket_index.into()) };
+            if (self.pred)(data) {
+                // the last element is swapped into inbucket_index, so it is checked next
+                return Some(unsafe {
+                    self.storage
+                        .remove_at_unchecked(bucket_index, self.inbucket_index.into())
+                });
+            }
+
+            self.inbucket_index += 1;
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn place_remove_contains() {
+        use super::*;
+
+        type Color = (String, u8, u8, u8);
+
+        let mut storage = Storage::new();
+        let red = storage.place::<Color>((String::from("red"), 255, 0, 0));
+        let green = storage.place::<Color>((String::from("green"), 0, 255, 0));
+
+        let mut ids = vec![];
+        for _ in 0..10_000 {
+            ids.push(storage.place::<Color>((String::from("blue"), 255, 0, 0)));
+        }
+
+        for id in ids.iter() {
+            storage.remove::<Color>(id);
+        }
+
+        assert!(storage.contains(&red));
+        assert!(storage.contains(&green));
+
+        storage.remove::<Color>(&red);
+        assert!(!storage.contains(&red));
+        assert!(storage.contains(&green));
+
+        storage.remove::<Color>(&green);
+        assert!(!storage.contains(&red));
+        assert!(!storage.contains(&green));
+    }
+
+    #[test]
     fn place_erase_contains() {
         use super::*;
 
-        type Color = (String, u8, u8, u8);
+        type Color = (String, u8, u8, u8);
+
+        let mut storage = Storage::new();
+        let red = storage.place::<Color>((String::from("red"), 255, 0, 0));
+        let green = storage.place::<Color>((String::from("green"), 0, 255, 0));
+
+        let mut ids = vec![];
+        for _ in 0..10_000 {
+            ids.push(storage.place::<Color>((String::from("blue"), 255, 0, 0)));
+        }
+
+        for id in ids.iter() {
+            storage.erase(id);
+        }
+
+        assert!(storage.contains(&red));
+        assert!(storage.contains(&green));
+
+        storage.erase(&red);
+        assert!(!storage.contains(&red));
+        assert!(storage.contains(&green));
+
+        storage.erase(&green);
+        assert!(!storage.contains(&red));
+        assert!(!storage.contains(&green));
+    }
+
+    #[test]
+    fn ids_of_types() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut expected = vec![
+            storage.place::<u32>(0),
+            storage.place::<u8>(1),
+            storage.place::<u32>(2),
+            storage.place::<u8>(3),
+        ];
+        let other = storage.place::<String>("4".into());
+
+        let mut ids = storage
+            .ids_of_types(|type_id| type_id != TypeId::of::<String>())
+            .map(|(_, id)| id)
+            .collect::<Vec<_>>();
+
+        let key = |id: &Id<U32Size, Unique32>| usize::from(id.token_index());
+        expected.sort_by_key(key);
+        ids.sort_by_key(key);
+        assert_eq!(expected, ids);
+        assert!(!ids.contains(&other));
+
+        let type_ids = storage
+            .ids_of_types(|type_id| type_id == TypeId::of::<u8>())
+            .map(|(type_id, _)| type_id)
+            .collect::<Vec<_>>();
+        assert_eq!(type_ids, vec![TypeId::of::<u8>(); 2]);
+
+        storage.clear();
+        assert_eq!(storage.all_ids().count(), 0);
+    }
+
+    #[test]
+    fn bucket_ref_place() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut bucket_ref = storage.bucket_ref::<u32>();
+        assert!(!bucket_ref.bucket_is_exists());
+        let first = bucket_ref.place(1u32);
+        assert!(bucket_ref.bucket_is_exists());
+        let rest = (2..5u32).map(|v| bucket_ref.place(v)).collect::<Vec<_>>();
+
+        let mut bucket_ref = storage.bucket_ref::<u32>();
+        assert!(bucket_ref.bucket_is_exists());
+        let last = bucket_ref.place(5u32);
+
+        assert_eq!(*storage.get::<u32>(&first), 1);
+        for (value, id) in (2..5u32).zip(rest.iter()) {
+            assert_eq!(*storage.get::<u32>(id), value);
+        }
+        assert_eq!(*storage.get::<u32>(&last), 5);
+        assert_eq!(storage.count::<u32>(), 5);
+    }
+
+    #[test]
+    fn remove_where() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..100u32)
+            .map(|value| storage.place::<u32>(value))
+            .collect::<Vec<_>>();
+        let other = storage.place::<u64>(1000);
+
+        let mut removed = storage.remove_where::<u32, _>(|value| *value >= 50);
+        removed.sort();
+        assert_eq!(removed, (50..100).collect::<Vec<_>>());
+
+        for (value, id) in ids.iter().enumerate() {
+            if value < 50 {
+                assert_eq!(*storage.get::<u32>(id), value as u32);
+            } else {
+                assert!(!storage.contains(id));
+            }
+        }
+        assert!(storage.contains(&other));
+        assert!(storage
+            .remove_where::<u32, _>(|value| *value >= 50)
+            .is_empty());
+        assert!(storage.remove_where::<i8, _>(|_| true).is_empty());
+    }
+
+    #[test]
+    fn place_unique_by() {
+        use super::*;
+
+        let eq = |l: &f64, r: &f64| (l - r).abs() < 1e-6;
+
+        let mut storage = Storage::new();
+        let first = storage.place_unique_by(1.0f64, eq);
+        let second = storage.place_unique_by(2.0f64, eq);
+        assert_ne!(first, second);
+
+        assert_eq!(storage.place_unique_by(1.0 + 1e-9, eq), first);
+        assert_eq!(storage.place_unique_by(2.0 - 1e-9, eq), second);
+        assert_eq!(storage.all_ids().count(), 2);
+
+        storage.remove::<f64>(&first);
+        let third = storage.place_unique_by(1.0f64, eq);
+        assert_ne!(third, first);
+        assert_eq!(*storage.get::<f64>(&third), 1.0);
+    }
+
+    #[test]
+    fn drain_filter() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..20)
+            .map(|value| storage.place::<String>(value.to_string()))
+            .collect::<Vec<_>>();
+
+        let mut drained = storage
+            .drain_filter::<String, _>(|value| value.len() == 2)
+            .collect::<Vec<_>>();
+        drained.sort();
+        assert_eq!(drained, (10..20).map(|v| v.to_string()).collect::<Vec<_>>());
+        for (value, id) in ids.iter().enumerate().take(10) {
+            assert_eq!(*storage.get::<String>(id), value.to_string());
+        }
+
+        {
+            let mut early = storage.drain_filter::<String, _>(|value| {
+                value.push('!');
+                true
+            });
+            assert!(early.next().is_some());
+            assert!(early.next().is_some());
+        }
+        let live = ids.iter().filter(|id| storage.contains(id)).count();
+        assert_eq!(live, 8);
+
+        assert_eq!(storage.drain_filter::<String, _>(|_| true).count(), 8);
+        assert!(ids.iter().all(|id| !storage.contains(id)));
+        assert_eq!(storage.drain_filter::<u8, _>(|_| true).count(), 0);
+    }
+
+    #[test]
+    fn rename_type() {
+        use super::*;
+
+        struct A(u32);
+        struct B(u32);
+
+        let mut storage = Storage::new();
+        let ids = (0..10)
+            .map(|value| storage.place(A(value)))
+            .collect::<Vec<_>>();
+
+        unsafe { storage.rename_type::<A, B>() };
+
+        for (value, id) in ids.iter().enumerate() {
+            assert!(storage.try_get::<A>(id).is_none());
+            assert_eq!(storage.get::<B>(id).0, value as u32);
+        }
+
+        let id = storage.place(B(10));
+        assert_eq!(storage.remove::<B>(&id).map(|b| b.0), Some(10));
+        assert_eq!(storage.remove::<B>(&ids[0]).map(|b| b.0), Some(0));
+
+        let a = storage.place(A(11));
+        assert_eq!(storage.get::<A>(&a).0, 11);
+    }
+
+    #[test]
+    fn bucket_capacity() {
+        use super::*;
+
+        for capacity in [1, 16, 1024] {
+            let mut storage = Storage::new().with_bucket_capacity(capacity);
+            assert_eq!(storage.capacity::<u64>(), 0);
+
+            storage.place::<u64>(0);
+            assert_eq!(storage.capacity::<u64>(), capacity);
+
+            storage.bucket_ref::<u8>().place(0u8);
+            assert_eq!(storage.capacity::<u8>(), capacity);
+        }
+
+        let mut storage = Storage::new();
+        storage.place::<u64>(0);
+        assert_eq!(storage.capacity::<u64>(), DEFAULT_START_CAPACITY);
+    }
+
+    #[test]
+    fn debug_location() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        ids.extend((0..5u8).map(|v| storage.place(v)));
+        let removed = ids.remove(3);
+        storage.erase(&removed);
+        assert_eq!(storage.debug_location(&removed), None);
+
+        let mut locations = ids
+            .iter()
+            .map(|id| storage.debug_location(id).unwrap())
+            .collect::<Vec<_>>();
+        for (bucket_index, inbucket_index) in locations.iter() {
+            assert!(*inbucket_index < storage.buckets[*bucket_index].1.len());
+        }
+
+        locations.sort();
+        locations.dedup();
+        assert_eq!(locations.len(), ids.len());
+
+        let reused = storage.place(3u32);
+        assert_eq!(reused.token_index(), removed.token_index());
+        assert_eq!(storage.debug_location(&removed), None);
+        assert!(storage.debug_location(&reused).is_some());
+    }
+
+    #[test]
+    fn placer() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        for value in 0..1000u32 {
+            storage.place(value);
+        }
+        let placed = storage.all_ids().count();
+
+        let mut ids = vec![];
+        let mut placer = storage.placer::<u64>();
+        for value in 0..1000u64 {
+            ids.push(placer.place(value));
+        }
+
+        assert_eq!(storage.all_ids().count() - placed, placed);
+        for (value, id) in ids.iter().enumerate() {
+            assert_eq!(*storage.get::<u64>(id), value as u64);
+        }
+    }
+
+    #[test]
+    fn iter_typed() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32)
+            .map(|value| storage.place_typed(value))
+            .collect::<Vec<_>>();
+        storage.place::<u8>(0);
+        storage.erase(ids[4].id());
+
+        let mut typed = storage.iter_typed::<u32>().collect::<Vec<_>>();
+        typed.sort_by_key(|(_, value)| **value);
+        assert_eq!(typed.len(), 9);
+        for (id, value) in typed {
+            assert_eq!(storage.get_typed(&id), value);
+            assert!(ids.contains(&id));
+        }
+        assert_eq!(storage.iter_typed::<u64>().count(), 0);
+    }
+
+    #[test]
+    fn count_where() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..100u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        storage.place(1000u64);
+
+        assert_eq!(storage.count_where::<u32, _>(|v| *v >= 90), 10);
+        storage.erase(&ids[95]);
+        assert_eq!(storage.count_where::<u32, _>(|v| *v >= 90), 9);
+        assert_eq!(storage.count_where::<u64, _>(|v| *v >= 90), 1);
+        assert_eq!(storage.count_where::<u8, _>(|_| true), 0);
+    }
+
+    #[test]
+    fn place_n_with() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.place::<(usize, usize)>((0, 0));
+
+        let ids = storage.place_n_with(100, |index| (index % 10, index / 10));
+        assert_eq!(ids.len(), 100);
+        assert_eq!(storage.capacity::<(usize, usize)>(), 101);
+        for (index, id) in ids.iter().enumerate() {
+            assert_eq!(*storage.get::<(usize, usize)>(id), (index % 10, index / 10));
+        }
+
+        assert!(storage.place_n_with(0, |_| 0u8).is_empty());
+    }
+
+    #[test]
+    fn partition_live() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        ids.push(storage.place(String::new()));
+        for id in ids.iter().step_by(3) {
+            storage.erase(id);
+        }
+
+        let (live, stale) = storage.partition_live(&ids);
+        assert_eq!((live.len(), stale.len()), (7, 4));
+        assert!(live.iter().all(|id| storage.contains(id)));
+        assert!(stale.iter().all(|id| !storage.contains(id)));
+        assert_eq!(stale, ids.iter().copied().step_by(3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn on_place() {
+        use super::*;
+        use std::{cell::RefCell, rc::Rc};
+
+        let placed = Rc::new(RefCell::new(vec![]));
+        let mut storage = Storage::new();
+        storage.on_place::<u32>({
+            let placed = placed.clone();
+            move |value, id| placed.borrow_mut().push((*value, id))
+        });
+
+        let mut ids = vec![storage.place(1u32), storage.place(2u32)];
+        storage.place(3u8);
+        ids.push(storage.placer::<u32>().place(4));
+        ids.push(storage.bucket_ref::<u32>().place(5u32));
+        ids.extend(storage.place_n_with(2, |index| index as u32 + 6));
+
+        let placed = placed.borrow();
+        assert_eq!(placed.len(), 6);
+        for ((value, id), expected) in placed.iter().zip(ids.iter()) {
+            assert_eq!(id, expected);
+            assert_eq!(storage.get::<u32>(id), value);
+        }
+    }
+
+    #[cfg(feature = "dirty-tracking")]
+    #[test]
+    fn take_dirty() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let other = storage.place(0u8);
+
+        *storage.get_mut::<u32>(&ids[1]) += 1;
+        *storage.get_mut::<u32>(&ids[1]) += 1;
+        *storage.try_get_mut::<u32>(&ids[7]).unwrap() += 1;
+        *storage.get_mut::<u8>(&other) += 1;
+        assert!(storage.try_get_mut::<u64>(&ids[3]).is_none());
+        storage.get::<u32>(&ids[2]);
+
+        let mut dirty = storage.take_dirty::<u32>();
+        dirty.sort_by_key(|id| usize::from(id.token_index()));
+        assert_eq!(dirty, vec![ids[1], ids[7]]);
+        assert!(storage.take_dirty::<u32>().is_empty());
+        assert_eq!(storage.take_dirty::<u8>(), vec![other]);
+    }
+
+    #[cfg(feature = "dirty-tracking")]
+    #[test]
+    fn clear_drops_dirty_ids() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(0u32);
+        *storage.get_mut::<u32>(&id) += 1;
+        storage.clear();
+
+        // the same token index and tag now refer to a new value
+        assert_eq!(storage.place(5u32), id);
+        assert!(storage.take_dirty::<u32>().is_empty());
+
+        *storage.get_mut::<u32>(&id) += 1;
+        storage.reset();
+        assert!(storage.take_dirty::<u32>().is_empty());
+    }
+
+    #[test]
+    fn tag_raw() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let first = storage.place(0u32);
+        assert_eq!(first.tag_raw(), first.tag().current() as u128);
+
+        storage.remove::<u32>(&first);
+        let second = storage.place(1u32);
+        assert_eq!(first.token_index(), second.token_index());
+        assert!(second.tag_raw() > first.tag_raw());
+        assert_eq!(second.tag_raw(), second.tag().current() as u128);
+    }
+
+    #[test]
+    fn pin_hot_type() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let byte = storage.place(1u8);
+        storage.pin_hot_type::<u32>();
+        storage.pin_hot_type::<u8>();
+        storage.pin_hot_type::<u8>();
+        assert_eq!(storage.hot_types.iter().flatten().count(), 2);
+        storage.pin_hot_type::<[u8; 2]>();
+        storage.pin_hot_type::<u16>();
+        assert_eq!(storage.hot_types.iter().flatten().count(), HOT_TYPES);
+
+        let ids = (0..100u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let other = storage.place(2u8);
+        let string = storage.place(String::from("cold"));
+        storage.pin_hot_type::<String>();
+        storage.pin_hot_type::<u64>();
+
+        for (value, id) in ids.iter().enumerate() {
+            assert_eq!(*storage.get::<u32>(id), value as u32);
+        }
+        assert_eq!(*storage.get::<u8>(&byte), 1);
+        assert_eq!(*storage.get::<u8>(&other), 2);
+        assert_eq!(storage.get::<String>(&string), "cold");
+        assert_eq!(storage.count_where::<u32, _>(|_| true), 100);
+        assert_eq!(storage.count_where::<u8, _>(|v| *v == 2), 1);
+
+        storage.reset();
+        let after_reset = storage.place(3u32);
+        assert_eq!(*storage.get::<u32>(&after_reset), 3);
+        assert_eq!(storage.count_where::<u32, _>(|_| true), 1);
+    }
+
+    #[test]
+    fn total_allocated_bytes() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let empty = storage.total_allocated_bytes();
+
+        let ids = (0..1000u64).map(|v| storage.place(v)).collect::<Vec<_>>();
+        storage.place(0u8);
+        let placed = storage.total_allocated_bytes();
+        assert!(placed >= empty + 1000 * size_of::<u64>());
+
+        for id in ids.iter() {
+            storage.remove::<u64>(id);
+        }
+        assert_eq!(storage.total_allocated_bytes(), placed);
+
+        storage.shrink_to_fit();
+        assert!(storage.total_allocated_bytes() < placed);
+    }
+
+    #[test]
+    fn same_layout_types_do_not_alias() {
+        use super::*;
+
+        struct A;
+        struct B;
+        struct Tagged<T>(u32, PhantomData<T>);
+
+        let mut storage = Storage::new();
+        let a = storage.place(Tagged::<A>(1, PhantomData));
+        let b = storage.place(Tagged::<B>(2, PhantomData));
+        assert_ne!(storage.get_type_id(&a), storage.get_type_id(&b));
+
+        assert_eq!(storage.get::<Tagged<A>>(&a).0, 1);
+        assert_eq!(storage.get::<Tagged<B>>(&b).0, 2);
+        assert!(storage.try_get::<Tagged<A>>(&b).is_none());
+        assert!(storage.try_get_mut::<Tagged<B>>(&a).is_none());
+        assert!(!storage.contains_exact::<Tagged<A>>(&b));
+        assert!(storage.remove::<Tagged<B>>(&a).is_none());
+        assert_eq!(storage.count_where::<Tagged<A>, _>(|_| true), 1);
+        let get = std::panic::AssertUnwindSafe(|| storage.get::<Tagged<A>>(&b).0);
+        assert!(std::panic::catch_unwind(get).is_err());
+    }
+
+    #[test]
+    fn can_place_without_growth() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert!(storage.can_place_without_growth::<u32>(0));
+        assert!(!storage.can_place_without_growth::<u32>(1));
+
+        storage.reserve::<u32>(100);
+        assert!(storage.can_place_without_growth::<u32>(100));
+        assert!(!storage.can_place_without_growth::<u32>(101));
+        assert!(!storage.can_place_without_growth::<u64>(1));
+
+        let capacity = storage.capacity::<u32>();
+        for value in 0..60 {
+            storage.place(value as u32);
+        }
+        assert_eq!(storage.capacity::<u32>(), capacity);
+        assert!(storage.can_place_without_growth::<u32>(40));
+        assert!(!storage.can_place_without_growth::<u32>(41));
+    }
+
+    #[test]
+    fn into_remapped() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let numbers = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let strings = (0..10)
+            .map(|v| storage.place(v.to_string()))
+            .collect::<Vec<_>>();
+        storage.remove::<u32>(&numbers[3]);
+        storage.erase(&strings[7]);
+
+        let (values, remap) = storage.into_remapped();
+        assert_eq!(values.len(), 18);
+        assert_eq!(remap.len(), 18);
+
+        let remap = remap.into_iter().collect::<HashMap<_, _>>();
+        for (value, id) in numbers.iter().enumerate() {
+            match remap.get(id) {
+                Some(index) => {
+                    let (type_id, data) = &values[*index];
+                    assert_eq!(*type_id, TypeId::of::<u32>());
+                    assert_eq!(data.downcast_ref::<u32>(), Some(&(value as u32)));
+                }
+                None => assert_eq!(value, 3),
+            }
+        }
+        for (value, id) in strings.iter().enumerate() {
+            match remap.get(id) {
+                Some(index) => {
+                    assert_eq!(values[*index].1.downcast_ref(), Some(&value.to_string()))
+                }
+                None => assert_eq!(value, 7),
+            }
+        }
+    }
+
+    // a drop reaching back into the storage through a pointer is undefined behaviour
+    // already, so the flag clear and drop set is tested directly
+    #[cfg(debug_assertions)]
+    #[test]
+    fn place_during_clear() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.set_tearing_down(true);
+        let place = std::panic::AssertUnwindSafe(|| storage.place(0u32));
+        assert!(std::panic::catch_unwind(place).is_err());
+
+        storage.set_tearing_down(false);
+        storage.place(1u32);
+        storage.clear();
+        assert!(!storage.tearing_down);
+        assert_eq!(storage.len(), 0);
+    }
+
+    #[test]
+    fn growth_factor() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.growth_factor(), 2.0);
+        storage.place(0u32);
+
+        storage.set_growth_factor(1.5);
+        assert_eq!(storage.growth_factor(), 1.5);
+
+        let mut capacities = vec![storage.capacity::<u32>()];
+        for value in 0..40u32 {
+            storage.place(value);
+            if storage.capacity::<u32>() != *capacities.last().unwrap() {
+                capacities.push(storage.capacity::<u32>());
+            }
+        }
+        assert_eq!(capacities, vec![4, 6, 9, 13, 19, 28, 42]);
+
+        storage.set_growth_factor(100.0);
+        assert_eq!(storage.growth_factor(), 4.0);
+        storage.set_growth_factor(0.5);
+        assert_eq!(storage.growth_factor(), 1.1);
+    }
+
+    #[test]
+    fn iter_from() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        for value in 0..10u32 {
+            storage.place(value);
+        }
+        let other = storage.place(0u8);
+
+        let first_half = storage.iter_typed::<u32>().take(5).collect::<Vec<_>>();
+        let last = *first_half.last().unwrap().0.id();
+
+        let rest = storage
+            .iter_from::<u32>(&last)
+            .skip(1)
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        assert_eq!(rest, vec![5, 6, 7, 8, 9]);
+
+        let (first, _) = storage.iter_from::<u32>(&last).next().unwrap();
+        assert_eq!(first, last);
+
+        assert_eq!(storage.iter_from::<u32>(&other).count(), 0);
+        storage.remove::<u32>(&last);
+        assert_eq!(storage.iter_from::<u32>(&last).count(), 0);
+    }
+
+    #[test]
+    fn retain_ids() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut ids = vec![];
+        for value in 0..100u32 {
+            ids.push(storage.place(value));
+            ids.push(storage.place(value.to_string()));
+            ids.push(storage.place(value as u8));
+        }
+
+        let keep = ids.iter().step_by(7).copied().collect::<HashSet<_>>();
+        storage.retain_ids(&keep);
+
+        for id in ids.iter() {
+            assert_eq!(storage.contains(id), keep.contains(id));
+        }
+        assert_eq!(storage.all_ids().count(), keep.len());
+        for (index, id) in ids.iter().enumerate().step_by(7) {
+            match index % 3 {
+                0 => assert_eq!(*storage.get::<u32>(id), (index / 3) as u32),
+                1 => assert_eq!(*storage.get::<String>(id), (index / 3).to_string()),
+                _ => assert_eq!(*storage.get::<u8>(id), (index / 3) as u8),
+            }
+        }
+    }
+
+    #[test]
+    fn contains_batch() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut ids = (0..100u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        for id in ids.iter().step_by(3) {
+            storage.remove::<u32>(id);
+        }
+        ids.extend((0..10u32).map(|v| storage.place(v)));
+
+        let mut out = vec![false; ids.len()];
+        storage.contains_batch(&ids, &mut out);
+        assert_eq!(
+            out,
+            ids.iter()
+                .map(|id| storage.contains(id))
+                .collect::<Vec<_>>()
+        );
+        assert!(out.iter().any(|contains| !contains));
+    }
+
+    #[test]
+    fn get_weak() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let first = storage.place(1u32);
+        let weak = first.weak();
+        assert_eq!(storage.get_weak::<u32>(&weak), Some(&1));
+        assert_eq!(storage.get_weak::<u8>(&weak), None);
+
+        storage.remove::<u32>(&first);
+        assert_eq!(storage.get_weak::<u32>(&weak), None);
+
+        let second = storage.place(2u32);
+        assert_eq!(second.weak(), weak);
+        assert_eq!(storage.get_weak::<u32>(&weak), Some(&2));
+        assert_eq!(storage.try_get::<u32>(&first), None);
+    }
+
+    #[test]
+    fn trim() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let fragmented = (0..100u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        for id in fragmented.iter().skip(10) {
+            storage.remove::<u32>(id);
+        }
+        for value in 0..60u8 {
+            storage.place(value);
+        }
+        let packed = storage.capacity::<u8>();
+        assert_eq!(packed, 64);
+
+        storage.trim(0.5);
+        assert_eq!(storage.capacity::<u32>(), 10);
+        assert_eq!(storage.capacity::<u8>(), packed);
+        for (value, id) in fragmented.iter().enumerate().take(10) {
+            assert_eq!(*storage.get::<u32>(id), value as u32);
+        }
+    }
+
+    #[test]
+    fn len_stays_consistent() {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        for seed in 0..4 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut storage = Storage::new();
+            let mut ids = vec![];
+            for _ in 0..10_000 {
+                match rng.gen_range(0..100) {
+                    0..=39 => ids.push(storage.place(rng.gen::<u32>())),
+                    40..=54 => ids.push(storage.place(rng.gen::<u8>().to_string())),
+                    55..=69 if !ids.is_empty() => {
+                        let id = ids.swap_remove(rng.gen_range(0..ids.len()));
+                        let _ = storage.try_remove::<u32>(&id);
+                        let _ = storage.try_remove::<String>(&id);
+                    }
+                    70..=84 if !ids.is_empty() => {
+                        storage.erase(&ids.swap_remove(rng.gen_range(0..ids.len())));
+                    }
+                    85..=89 => {
+                        storage.remove_where::<u32, _>(|value| value % 7 == 0);
+                    }
+                    90..=94 => {
+                        storage
+                            .drain_filter::<String, _>(|value| value.len() == 2)
+                            .count();
+                    }
+                    95..=96 => {
+                        let keep = ids.iter().step_by(2).copied().collect::<HashSet<_>>();
+                        storage.retain_ids(&keep);
+                    }
+                    // clearing starts the tags over, old ids could name new values
+                    97 => {
+                        storage.clear();
+                        ids.clear();
+                    }
+                    98 => {
+                        storage.reset();
+                        ids.clear();
+                    }
+                    _ => {
+                        storage.place_n_with(rng.gen_range(0..10), |value| value as u32);
+                    }
+                }
+
+                storage.validate();
+                assert_eq!(storage.len(), storage.all_ids().count());
+            }
+        }
+    }
+
+    #[test]
+    fn iteration_bytes() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.iteration_bytes::<u64>(), 0);
+
+        for value in 0..100u64 {
+            storage.place(value);
+            storage.place(value as u8);
+        }
+        assert_eq!(storage.iteration_bytes::<u64>(), 100 * 16);
+        assert_eq!(storage.iteration_bytes::<u8>(), 100 * 8);
+    }
+
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn deterministic_iteration() {
+        use super::*;
+
+        fn build() -> Storage {
+            let mut storage = Storage::new();
+            let ids = (0..100u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+            for id in ids.iter().step_by(3) {
+                storage.remove::<u32>(id);
+            }
+            for value in 100..120u32 {
+                storage.place(value);
+            }
+            storage
+        }
+
+        let left = build();
+        let right = build();
+        let order = |storage: &Storage| {
+            storage
+                .iter_typed::<u32>()
+                .map(|(id, value)| (usize::from(id.id().token_index()), *value))
+                .collect::<Vec<_>>()
+        };
+
+        let left_order = order(&left);
+        assert_eq!(left_order, order(&right));
+        assert!(left_order.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        // every iteration path agrees on the order
+        let mut left = left;
+        let values = left_order
+            .iter()
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        let ids = left_order
+            .iter()
+            .map(|(token_index, _)| *token_index)
+            .collect::<Vec<_>>();
+        assert_eq!(left.iter::<u32>().copied().collect::<Vec<_>>(), values);
+        assert_eq!(
+            left.snapshot().iter::<u32>().copied().collect::<Vec<_>>(),
+            values
+        );
+        assert_eq!(
+            left.iter_mut::<u32>().map(|v| *v).collect::<Vec<_>>(),
+            values
+        );
+        let first_pairs = left.iter_pairs::<u32>().take(2).collect::<Vec<_>>();
+        assert_eq!(
+            first_pairs,
+            vec![(&values[0], &values[1]), (&values[0], &values[2])]
+        );
+        let token_indices = |ids: Vec<Id<U32Size, Unique32>>| {
+            ids.into_iter()
+                .map(|id| usize::from(id.token_index()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(token_indices(left.ids::<u32>().collect()), ids);
+        assert_eq!(
+            token_indices(left.iter_with_ids::<u32>().map(|(id, _)| id).collect()),
+            ids
+        );
+        assert_eq!(
+            token_indices(left.iter_with_ids_mut::<u32>().map(|(id, _)| id).collect()),
+            ids
+        );
+    }
+
+    #[test]
+    fn donate_bucket() {
+        use super::*;
+
+        let mut src = Storage::new();
+        let ids = (0..10_000u32).map(|v| src.place(v)).collect::<Vec<_>>();
+        let kept = src.place(String::from("kept"));
+        for id in ids.iter().step_by(10) {
+            src.remove::<u32>(id);
+        }
+
+        let mut dest = Storage::new();
+        dest.place(1u8);
+        let remap = src.donate_bucket::<u32>(&mut dest);
+        assert_eq!(remap.len(), 9_000);
+        assert_eq!(src.count_where::<u32, _>(|_| true), 0);
+        assert_eq!(src.len(), 1);
+        assert_eq!(src.get::<String>(&kept), "kept");
+        assert_eq!(dest.len(), 9_001);
+        src.validate();
+        dest.validate();
+
+        for (value, id) in ids.iter().enumerate() {
+            assert!(!src.contains(id));
+            match remap.get(*id) {
+                Some(new) => assert_eq!(*dest.get::<u32>(&new), value as u32),
+                None => assert_eq!(value % 10, 0),
+            }
+        }
+
+        // dest already has a bucket of u32, values are moved one by one
+        let more = (0..10u32).map(|v| src.place(v)).collect::<Vec<_>>();
+        let remap = src.donate_bucket::<u32>(&mut dest);
+        for (value, id) in more.iter().enumerate() {
+            assert_eq!(*dest.get::<u32>(&remap.get(*id).unwrap()), value as u32);
+        }
+        assert_eq!(dest.count_where::<u32, _>(|_| true), 9_010);
+        assert!(src.donate_bucket::<u64>(&mut dest).is_empty());
+    }
+
+    #[test]
+    fn type_version() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.type_version::<u32>(), 0);
+
+        let id = storage.place(1u32);
+        let placed = storage.type_version::<u32>();
+        assert!(placed > 0);
+
+        storage.get::<u32>(&id);
+        *storage.get_mut::<u32>(&id) += 1;
+        storage.place(1u8);
+        storage.reserve::<u32>(100);
+        assert_eq!(storage.type_version::<u32>(), placed);
+
+        storage.remove::<u32>(&id);
+        let removed = storage.type_version::<u32>();
+        assert!(removed > placed);
+
+        assert!(storage.try_remove::<u32>(&id).is_err());
+        assert_eq!(storage.type_version::<u32>(), removed);
+        storage.place(2u32);
+        assert!(storage.type_version::<u32>() > removed);
+    }
+
+    #[test]
+    fn try_reserve() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.try_reserve::<u32>(10), Ok(()));
+        assert!(storage.capacity::<u32>() >= 10);
+        assert_eq!(
+            storage.try_reserve::<u32>(u32::MAX as usize + 1),
+            Err(TryReserveError::CapacityOverflow)
+        );
+        assert_eq!(
+            storage.try_reserve::<u32>(usize::MAX),
+            Err(TryReserveError::CapacityOverflow)
+        );
+
+        // 2^60 bytes passes the layout checks but no allocator can provide it
+        let mut storage = Storage::new_with_tag_and_size::<crate::USize, Unique32>();
+        let result = storage.try_reserve::<[u8; 1 << 10]>(1 << 50);
+        assert!(matches!(result, Err(TryReserveError::AllocError { .. })));
+        assert_eq!(storage.capacity::<[u8; 1 << 10]>(), 0);
+
+        let id = storage.place([1u8; 1 << 10]);
+        assert_eq!(storage.get::<[u8; 1 << 10]>(&id)[0], 1);
+    }
+
+    #[test]
+    fn iteration_stays_dense() {
+        use super::*;
+
+        // removals swap the last value into the hole, so there are no tombstones to skip
+        let mut storage = Storage::new();
+        let ids = (0..1000u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        for (index, id) in ids.iter().enumerate() {
+            if index % 3 == 0 || index >= 500 {
+                storage.remove::<u32>(id);
+            }
+        }
+
+        let live = ids.iter().filter(|id| storage.contains(id)).count();
+        assert_eq!(storage.iter_typed::<u32>().count(), live);
+        assert_eq!(storage.iteration_bytes::<u32>(), live * 8);
+        assert!(storage
+            .iter_typed::<u32>()
+            .all(|(id, value)| *storage.get::<u32>(id.id()) == *value));
+    }
+
+    #[test]
+    fn was_ever_issued() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(0u32);
+        let (token_index, tag) = id.into_raw();
+        assert_eq!(Id::from_raw(token_index, tag), id);
+        assert!(storage.was_ever_issued(&id));
+
+        storage.remove::<u32>(&id);
+        assert!(!storage.contains(&id));
+        assert!(storage.was_ever_issued(&id));
+
+        let forged = Id::from_raw(u32::MAX as usize - 1, 0);
+        assert!(!storage.was_ever_issued(&forged));
+        assert!(!storage.contains(&forged));
+        assert!(storage.try_get::<u32>(&forged).is_none());
+    }
+
+    #[test]
+    fn place_boxed() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let boxed = Box::new([7u64; 64]);
+        let address = &*boxed as *const [u64; 64];
+        let id = storage.place_boxed(boxed);
+        let value = storage.place(1u32);
+
+        assert_eq!(storage.get_boxed::<[u64; 64]>(&id).unwrap()[63], 7);
+        assert_eq!(
+            storage.get_boxed::<[u64; 64]>(&id).unwrap() as *const [u64; 64],
+            address
+        );
+        assert!(storage.get_boxed::<u32>(&value).is_none());
+        assert!(storage.try_get::<[u64; 64]>(&id).is_none());
+
+        let boxed = storage.remove::<Box<[u64; 64]>>(&id).unwrap();
+        assert_eq!(&*boxed as *const [u64; 64], address);
+        assert!(storage.get_boxed::<[u64; 64]>(&id).is_none());
+    }
+
+    #[test]
+    fn dangling_tokens() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        assert_eq!(storage.dangling_token_count(), 0);
+
+        for id in ids.iter().skip(2).step_by(2) {
+            storage.remove::<u32>(id);
+        }
+        assert_eq!(storage.dangling_token_count(), 4);
+        assert_eq!(
+            storage.dangling_tokens().collect::<Vec<_>>(),
+            vec![2, 4, 6, 8]
+        );
+
+        storage.place(0u32);
+        assert_eq!(storage.dangling_token_count(), 3);
+    }
+
+    #[test]
+    fn place_into() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let string = storage.place_into::<String, _>("2");
+        let number = storage.place_into::<u32, _>(7u8);
+
+        assert_eq!(storage.get::<String>(&string), "2");
+        assert_eq!(*storage.get::<u32>(&number), 7);
+        assert!(storage.try_get::<u8>(&number).is_none());
+    }
+
+    #[test]
+    fn generation_snapshot() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..4u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let before = storage.generation_snapshot();
+        assert_eq!(before, vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+
+        storage.remove::<u32>(&ids[1]);
+        let reused = storage.place(5u32);
+        assert_eq!(reused.into_raw().0, 1);
+
+        let after = storage.generation_snapshot();
+        assert_eq!(after, vec![(0, 0), (1, 1), (2, 0), (3, 0)]);
+        assert_eq!(after[1].1, reused.tag_raw());
+
+        storage.remove::<u32>(&ids[2]);
+        assert_eq!(storage.generation_snapshot()[2], (2, 1 | 1 << 31));
+    }
+
+    #[test]
+    fn generation_snapshot_skips_locked() {
+        use super::*;
+
+        // saturates after two generations
+        #[derive(Copy, Clone, Eq, PartialEq, Default, Hash, Debug)]
+        struct TinyTag(u8);
+        impl UniqueTag for TinyTag {
+            fn next(self) -> Self {
+                if self.is_locked() {
+                    return self;
+                }
+                Self(u8::min((self.0 & 0x7f) + 1, 2) | (self.0 & 0x80))
+            }
+            fn last(self) -> usize {
+                2
+            }
+            fn current(self) -> usize {
+                self.0 as _
+            }
+            fn is_removed(&self) -> bool {
+                self.0 & 0x80 != 0
+            }
+            fn set_removed(&mut self, removed: bool) {
+                self.0 = if removed {
+                    self.0 | 0x80
+                } else {
+                    self.0 & 0x7f
+                }
+            }
+            fn is_locked(&self) -> bool {
+                self.0 == 0x80
+            }
+            fn mark_locked(&mut self) {
+                self.0 = 0x80
+            }
+            fn bytes() -> usize {
+                1
+            }
+            fn to_raw(self) -> u128 {
+                self.0 as _
+            }
+            fn from_raw(raw: u128) -> Self {
+                Self(raw as _)
+            }
+        }
+
+        let mut storage = Storage::new_with_tag_and_size::<U32Size, TinyTag>();
+        let kept = storage.place(0u32);
+        for value in 0..3u32 {
+            let id = storage.place(value);
+            assert_eq!(id.into_raw().0, 1);
+            storage.remove::<u32>(&id);
+        }
+
+        // token 1 saturated and got locked, it shows up as neither fresh nor removed
+        assert!(storage.tokens.tags().nth(1).unwrap().is_locked());
+        assert_eq!(storage.generation_snapshot(), vec![(0, 0)]);
+        assert_eq!(storage.place(3u32).into_raw().0, 2);
+        assert_eq!(storage.generation_snapshot(), vec![(0, 0), (2, 0)]);
+        assert!(storage.contains(&kept));
+    }
+
+    #[test]
+    fn prewarm() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.ensure_bucket::<u8>();
+        storage.ensure_bucket::<u32>();
+        storage.ensure_bucket::<String>();
+        storage.ensure_bucket::<u8>();
+        for value in 0..10u32 {
+            storage.place(value);
+        }
+        assert_eq!(storage.capacity::<u8>(), 0);
+
+        storage.prewarm(64);
+        assert!(storage.capacity::<u8>() >= 64);
+        assert!(storage.capacity::<u32>() >= 64);
+        assert!(storage.capacity::<String>() >= 64);
+        assert_eq!(storage.capacity::<u64>(), 0);
+        assert_eq!(storage.report().types.len(), 3);
+
+        storage.prewarm(8);
+        assert_eq!(storage.capacity::<u8>(), 64);
+    }
+
+    #[test]
+    fn freeze() {
+        use super::*;
+
+        fn sum(storage: &Storage, ids: &[Id<U32Size, Unique32>]) -> u32 {
+            ids.iter().map(|id| *storage.get::<u32>(id)).sum()
+        }
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        *storage.get_mut::<u32>(&ids[0]) = 10;
+
+        let frozen = storage.freeze();
+        assert_eq!(sum(frozen, &ids), 55);
+        assert_eq!(frozen.snapshot().iter::<u32>().count(), 10);
+    }
+
+    #[test]
+    fn place_hint() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place_hint(0u32, 1000);
+        assert_eq!(storage.capacity::<u32>(), 1000);
+        assert_eq!(*storage.get::<u32>(&id), 0);
+
+        for value in 1..1000u32 {
+            storage.place_hint(value, 10);
+        }
+        assert_eq!(storage.capacity::<u32>(), 1000);
+
+        storage.place_hint(1000u32, 1500);
+        assert_eq!(storage.capacity::<u32>(), 1500);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn colliding_type_map() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.place(0u32);
+
+        // what a broken hasher or map change would do: map u64 onto the bucket of u32
+        let bucket_index = storage.bucket_indexes[&TypeId::of::<u32>()];
+        storage
+            .bucket_indexes
+            .insert(TypeId::of::<u64>(), bucket_index);
+
+        let place = std::panic::AssertUnwindSafe(|| storage.place(0u64));
+        let message = std::panic::catch_unwind(place).unwrap_err();
+        assert_eq!(
+            message.downcast_ref::<String>().unwrap(),
+            "type u64 resolved to the bucket of u32"
+        );
+    }
+
+    #[test]
+    fn neighbor() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = [
+            storage.place(0u32),
+            storage.place(1u32),
+            storage.place(2u32),
+        ];
+        storage.place(0u8);
+
+        let next = storage.neighbor(&ids[0]).unwrap();
+        assert_eq!(next, ids[1]);
+        assert_eq!(*storage.get::<u32>(&next), 1);
+        assert_eq!(storage.neighbor(&ids[2]), None);
+
+        // the last element moves into the hole
+        storage.erase(&ids[1]);
+        assert_eq!(storage.neighbor(&ids[0]), Some(ids[2]));
+        assert_eq!(storage.neighbor(&ids[1]), None);
+    }
+
+    #[test]
+    fn reserve_exact() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.reserve_exact::<u32>(100);
+        assert_eq!(storage.capacity::<u32>(), 100);
+
+        for value in 0..100 {
+            storage.place(value as u32);
+        }
+        storage.reserve_exact::<u32>(1);
+        assert_eq!(storage.capacity::<u32>(), 101);
+
+        // reserve grows by the growth factor instead
+        for value in 0..101 {
+            storage.place(value as u64);
+        }
+        assert_eq!(storage.capacity::<u64>(), 128);
+        storage.reserve::<u64>(28);
+        assert_eq!(storage.capacity::<u64>(), 256);
+    }
+
+    #[test]
+    fn take_bucket() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10)
+            .map(|value| storage.place(value.to_string()))
+            .collect::<Vec<_>>();
+        let other = storage.place(1u8);
+        storage.erase(&ids[3]);
+        let version = storage.type_version::<String>();
+
+        let mut taken = storage.take_bucket::<String>();
+        taken.sort();
+        let mut expected = (0..10)
+            .filter(|value| *value != 3)
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(taken, expected);
+
+        assert!(ids.iter().all(|id| !storage.contains(id)));
+        assert_eq!(storage.capacity::<String>(), 0);
+        assert!(storage.type_version::<String>() > version);
+        assert_eq!(storage.len(), 1);
+        assert_eq!(*storage.get::<u8>(&other), 1);
+        assert!(storage.take_bucket::<u16>().is_empty());
+
+        let id = storage.place("again".to_string());
+        assert_eq!(storage.get::<String>(&id), "again");
+        storage.validate();
+    }
+
+    #[test]
+    fn token_capacity() {
+        use super::*;
+
+        let mut storage = Storage::new().with_token_capacity(100);
+        assert_eq!(storage.token_capacity(), 100);
+
+        for value in 0..50 {
+            storage.place(value as u32);
+            let id = storage.place(value as u16);
+            storage.erase(&id);
+            storage.place(value as u8);
+        }
+        assert_eq!(storage.token_capacity(), 100);
+    }
+
+    #[test]
+    fn is_type() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(0u32);
+        let other = storage.place(0u8);
+        assert!(storage.is_type::<u32>(&id));
+        assert!(!storage.is_type::<u8>(&id));
+        assert!(!storage.is_type::<u64>(&id));
+        assert!(storage.is_type::<u8>(&other));
+
+        storage.erase(&id);
+        assert!(!storage.is_type::<u32>(&id));
+
+        // the slot now holds a u8 under a newer generation
+        let reused = storage.place(1u8);
+        assert_eq!(reused.token_index(), id.token_index());
+        assert!(!storage.is_type::<u8>(&id));
+        assert!(storage.is_type::<u8>(&reused));
+    }
+
+    #[test]
+    fn cell_token_index() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut ids = (0..8)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        ids.push(storage.place(0u8));
+
+        // each removal swaps the last u32 into the hole
+        for id in ids.drain(..3) {
+            storage.erase(&id);
+            assert_eq!(storage.cell_token_index(&id), None);
+        }
+        storage.remove::<u32>(&ids[2]);
+        ids.remove(2);
+
+        for id in ids.iter() {
+            assert_eq!(storage.cell_token_index(id), Some(id.token_index()));
+        }
+        storage.validate();
+    }
+
+    #[test]
+    fn placer_place_slice() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.place(0u8);
+
+        let mut placer = storage.placer::<u64>();
+        let first = placer.place(0);
+        let ids = placer.place_slice((1..=10_000).collect());
+        assert!(placer.place_slice(vec![]).is_empty());
+
+        assert_eq!(ids.len(), 10_000);
+        for (value, id) in ids.iter().enumerate() {
+            assert_eq!(*storage.get::<u64>(id), value as u64 + 1);
+        }
+        assert_eq!(*storage.get::<u64>(&first), 0);
+        assert_eq!(storage.snapshot().iter::<u64>().count(), 10_001);
+        assert_eq!(storage.len(), 10_002);
+    }
+
+    #[test]
+    fn panicking_drop() {
+        use super::*;
+        use std::{cell::Cell, rc::Rc};
+
+        struct Bomb(Rc<Cell<usize>>, bool);
+
+        impl Drop for Bomb {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+                if self.1 {
+                    panic!("bomb");
+                }
+            }
+        }
+
+        let dropped = Rc::new(Cell::new(0));
+        let mut storage = Storage::new();
+        for index in 0..10 {
+            storage.place(Bomb(dropped.clone(), index == 5));
+        }
+        for _ in 0..10 {
+            storage.place((Bomb(dropped.clone(), false), 0u8));
+        }
+
+        let clear = std::panic::AssertUnwindSafe(|| storage.clear());
+        assert!(std::panic::catch_unwind(clear).is_err());
+        assert_eq!(dropped.get(), 20);
+        assert_eq!(storage.len(), 0);
+        storage.validate();
+
+        let id = storage.place(Bomb(dropped.clone(), false));
+        assert!(storage.contains(&id));
+
+        storage.place((Bomb(dropped.clone(), true), 0u8));
+        let drop = std::panic::AssertUnwindSafe(|| core::mem::drop(storage));
+        assert!(std::panic::catch_unwind(drop).is_err());
+        assert_eq!(dropped.get(), 22);
+    }
+
+    #[test]
+    fn contains_exact_removed() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..4)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        assert!(storage.contains_exact::<u32>(&ids[1]));
+        assert!(!storage.contains_exact::<u8>(&ids[1]));
+
+        // the freed tokens now hold free list indexes, not locations
+        storage.erase(&ids[1]);
+        storage.erase(&ids[2]);
+        for id in ids.iter().skip(1).take(2) {
+            assert!(!storage.contains_exact::<u32>(id));
+            assert!(!storage.contains_exact::<u8>(id));
+        }
+        assert!(storage.contains_exact::<u32>(&ids[3]));
+    }
+
+    #[test]
+    fn typed_ids() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let number = storage.place_typed(1u32);
+        let text = storage.place_typed("text".to_string());
+        let erased = storage.place(2u32);
+
+        assert!(storage.contains_typed(&number));
+        assert_eq!(*storage.get_typed(&number), 1);
+        assert_eq!(storage.get_typed(&text), "text");
+
+        // both styles address the same elements
+        let ids = [number.erase(), text.erase(), erased];
+        assert_eq!(*storage.get::<u32>(&ids[0]), 1);
+        assert_eq!(storage.get_type_id(&ids[1]), Some(&TypeId::of::<String>()));
+
+        assert_eq!(storage.remove_typed(&number), Some(1));
+        assert!(storage.try_remove::<u32>(number.id()).is_err());
+        assert!(!storage.contains_typed(&number));
+        assert!(!storage.contains(&ids[0]));
+
+        storage.erase(&ids[1]);
+        assert!(!storage.contains_typed(&text));
+        assert_eq!(storage.remove::<u32>(&erased), Some(2));
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn pairs() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.iter_pairs::<u64>().count(), 0);
+        storage.for_each_pair_mut::<u64>(|_, _| unreachable!());
+
+        let ids = (1..=10)
+            .map(|value| storage.place(value as u64))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        storage.erase(&ids[9]);
+
+        assert_eq!(storage.iter_pairs::<u64>().count(), 9 * 8 / 2);
+        assert!(storage
+            .iter_pairs::<u64>()
+            .all(|(first, second)| first != second));
+        let sum = storage
+            .iter_pairs::<u64>()
+            .map(|(first, second)| first * second)
+            .sum::<u64>();
+        assert_eq!(
+            sum,
+            (45 * 45 - (1..10).map(|value| value * value).sum::<u64>()) / 2
+        );
+
+        // every value meets every other value once
+        let pairs = (1..=5)
+            .map(|value| storage.place((value as u64, 0u64)))
+            .collect::<Vec<_>>();
+        storage.for_each_pair_mut::<(u64, u64)>(|first, second| {
+            first.1 += second.0;
+            second.1 += first.0;
+        });
+        for (value, id) in (1..=5).zip(pairs.iter()) {
+            assert_eq!(*storage.get::<(u64, u64)>(id), (value, 15 - value));
+        }
+    }
+
+    #[test]
+    fn recommended_size() {
+        use super::*;
+
+        assert_eq!(Storage::recommended_size(0), "U32Size");
+        assert_eq!(Storage::recommended_size(u32::MAX as usize), "U32Size");
+        assert_eq!(Storage::recommended_size(u32::MAX as usize + 1), "USize");
+        assert_eq!(Storage::recommended_size(usize::MAX), "USize");
+    }
+
+    #[test]
+    fn replace_and_exchange() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(1u32);
+        let other = storage.place(2u32);
+
+        assert_eq!(storage.replace(&id, 3u32), Some(1));
+        assert_eq!(storage.replace(&id, 0u8), None);
+        assert_eq!(*storage.get::<u32>(&id), 3);
+
+        let (exchanged, old) = storage.exchange(&id, 4u32).unwrap();
+        assert_eq!(old, 3);
+        assert_eq!(exchanged.token_index(), id.token_index());
+        assert!(!storage.contains(&id));
+        assert_eq!(storage.replace(&id, 5u32), None);
+        assert_eq!(storage.exchange(&id, 5u32), None);
+        assert_eq!(storage.exchange(&exchanged, 0u8), None);
+        assert_eq!(*storage.get::<u32>(&exchanged), 4);
+        assert_eq!(*storage.get::<u32>(&other), 2);
+        assert_eq!(storage.len(), 2);
+        storage.validate();
+    }
+
+    #[test]
+    fn place_with_eviction() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let first = storage.place(0u32);
+        assert_eq!(storage.place_with_eviction(1u32).1, None);
+
+        storage.set_eviction_cap::<u32>(3);
+        let (_, evicted) = storage.place_with_eviction(2u32);
+        assert_eq!(evicted, None);
+        let (_, evicted) = storage.place_with_eviction(3u32);
+        assert_eq!(evicted, Some(0));
+        assert!(!storage.contains(&first));
+
+        // removed ids are skipped, plain places are tracked too, one value is evicted
+        // per place even past the cap
+        let ids = (4..8)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.erase(&ids[0]);
+        let mut evicted = vec![];
+        for value in 8..14 {
+            evicted.extend(storage.place_with_eviction(value as u32).1);
+        }
+        assert_eq!(evicted, vec![1, 2, 3, 5, 6, 7]);
+        let mut values = storage
+            .snapshot()
+            .iter::<u32>()
+            .copied()
+            .collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, (8..14).collect::<Vec<_>>());
+
+        storage.clear();
+        storage.place(0u32);
+        assert_eq!(storage.place_with_eviction(1u32).1, None);
+
+        storage.remove_eviction_cap::<u32>();
+        for value in 2..10 {
+            assert_eq!(storage.place_with_eviction(value as u32).1, None);
+        }
+    }
+
+    #[test]
+    fn pack_indices() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let mut indices = vec![7];
+        assert_eq!(storage.pack_indices::<u32>(&mut indices), Some(0));
+        assert_eq!(indices, vec![7]);
+
+        let ids = (0..6)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        storage.erase(&ids[2]);
+
+        indices.clear();
+        assert_eq!(storage.pack_indices::<u32>(&mut indices), Some(5));
+        assert_eq!(indices, vec![0, 1, 2, 3, 4]);
+
+        let mut values = vec![];
+        for index in indices {
+            let id = storage.id_at::<u32>(index as usize).unwrap();
+            assert!(ids.contains(&id));
+            values.push(*storage.get::<u32>(&id));
+        }
+        values.sort();
+        assert_eq!(values, vec![0, 1, 3, 4, 5]);
+        assert_eq!(storage.id_at::<u32>(5), None);
+        assert_eq!(storage.id_at::<u64>(0), None);
+    }
+
+    #[test]
+    fn iter_and_iter_mut() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.iter::<u32>().count(), 0);
+        assert_eq!(storage.iter_mut::<u32>().count(), 0);
+
+        let ids = (0..10)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        storage.erase(&ids[4]);
+
+        let mut values = storage.iter::<u32>().copied().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 3, 5, 6, 7, 8, 9]);
+
+        for value in storage.iter_mut::<u32>() {
+            *value *= 10;
+        }
+        assert_eq!(*storage.get::<u32>(&ids[9]), 90);
+        assert_eq!(storage.iter::<u32>().sum::<u32>(), 410);
+        assert_eq!(storage.iter::<u8>().count(), 1);
+    }
+
+    #[test]
+    fn register() {
+        use super::*;
+
+        let mut storage = Storage::new()
+            .with_type_capacity(5)
+            .register::<u8>()
+            .register::<u16>()
+            .register::<u32>()
+            .register::<u64>()
+            .register::<String>()
+            .register::<u8>();
+        assert_eq!(storage.buckets.len(), 5);
+        assert_eq!(storage.buckets.capacity(), 5);
+        let index_capacity = storage.bucket_indexes.capacity();
+        assert!(storage.is_empty());
+
+        for value in 0..10 {
+            storage.place(value as u8);
+            storage.place(value as u16);
+            storage.place(value as u32);
+            storage.place(value as u64);
+            storage.place(value.to_string());
+        }
+        assert_eq!(storage.buckets.len(), 5);
+        assert_eq!(storage.buckets.capacity(), 5);
+        assert_eq!(storage.bucket_indexes.capacity(), index_capacity);
+    }
+
+    #[cfg(feature = "track-removals")]
+    #[test]
+    fn double_removal() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(0u32);
+        let other = storage.place(1u32);
+        assert_eq!(storage.remove::<u8>(&id), None);
+        storage.erase(&id);
+
+        let line = line!() + 1;
+        let remove = std::panic::AssertUnwindSafe(|| storage.remove::<u32>(&id));
+        let message = std::panic::catch_unwind(remove).unwrap_err();
+        let message = message.downcast_ref::<String>().unwrap();
+        assert!(message.contains("removed twice"));
+        assert!(message.contains(&format!("storage.rs:{}", line - 3)));
+        assert!(message.contains(&format!("storage.rs:{}", line)));
+
+        // ids never removed before still report nothing
+        storage.erase(&Id::dangling());
+        assert_eq!(storage.remove::<u32>(&other), Some(1));
+
+        // try_remove only reports, and reissued tokens drop their entry
+        assert_eq!(storage.try_remove::<u32>(&id), Err(RemoveError::Stale));
+        for value in 0..100u32 {
+            let id = storage.place(value);
+            assert_eq!(storage.remove::<u32>(&id), Some(value));
+        }
+        assert!(storage.removals.len() <= 2);
+    }
+
+    #[test]
+    fn ids() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.ids::<u32>().count(), 0);
+
+        let mut ids = (0..10)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        for id in ids.drain(2..5) {
+            storage.erase(&id);
+        }
+        ids.push(storage.place(10u32));
+
+        let mut listed = storage.ids::<u32>().collect::<Vec<_>>();
+        assert!(listed.iter().all(|id| storage.contains(id)));
+        listed.sort_by_key(|id| usize::from(id.token_index()));
+        ids.sort_by_key(|id| usize::from(id.token_index()));
+        assert_eq!(listed, ids);
+    }
+
+    #[test]
+    fn iter_with_ids() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        assert_eq!(storage.iter_with_ids::<u32>().count(), 0);
+        assert_eq!(storage.iter_with_ids_mut::<u32>().count(), 0);
+
+        let ids = (0..10)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        storage.erase(&ids[3]);
+        storage.erase(&ids[0]);
+        let reused = storage.place(10u32);
+
+        let pairs = storage.iter_with_ids::<u32>().collect::<Vec<_>>();
+        assert_eq!(pairs.len(), 9);
+        for (id, value) in pairs {
+            assert!(storage.contains(&id));
+            assert_eq!(storage.get::<u32>(&id), value);
+        }
+
+        for (id, value) in storage.iter_with_ids_mut::<u32>() {
+            *value += u32::from(id == reused) * 100;
+        }
+        assert_eq!(*storage.get::<u32>(&reused), 110);
+        assert_eq!(*storage.get::<u32>(&ids[9]), 9);
+    }
+
+    #[test]
+    fn bump_scope() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let kept = (0..3)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+
+        let placed = {
+            let mut scope = storage.bump_scope::<u32>(10);
+            assert!(scope.is_empty());
+            let ids = (10..20).map(|value| scope.place(value)).collect::<Vec<_>>();
+            assert_eq!(scope.len(), 10);
+
+            let sum = ids
+                .iter()
+                .map(|id| scope.storage().get::<u32>(id))
+                .sum::<u32>();
+            assert_eq!(sum, 145);
+            assert_eq!(scope.storage().len(), 14);
+            ids
+        };
+
+        assert!(placed.iter().all(|id| !storage.contains(id)));
+        assert_eq!(storage.iter::<u32>().count(), 3);
+        assert_eq!(storage.len(), 4);
+        for (value, id) in kept.iter().enumerate() {
+            assert_eq!(*storage.get::<u32>(id), value as u32);
+        }
+        storage.validate();
+
+        let mut scope = storage.bump_scope::<u64>(1);
+        scope.place(0);
+        let overflow = std::panic::AssertUnwindSafe(|| scope.place(1));
+        assert!(std::panic::catch_unwind(overflow).is_err());
+    }
+
+    #[test]
+    fn discriminants() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(0u32);
+        assert_eq!(storage.discriminant(&id), None);
+
+        let mut storage = Storage::new().with_discriminants();
+        let plain = storage.place(0u32);
+        let ids = (1..=9)
+            .map(|value| storage.place_with_discriminant(value as u32, value % 3))
+            .collect::<Vec<_>>();
+        assert_eq!(storage.discriminant(&plain), Some(0));
+        assert_eq!(storage.discriminant(&ids[4]), Some(2));
+
+        let mut zeros = storage
+            .iter_with_ids::<u32>()
+            .filter(|(id, _)| storage.discriminant(id) == Some(0))
+            .map(|(_, value)| *value)
+            .collect::<Vec<_>>();
+        zeros.sort();
+        assert_eq!(zeros, vec![0, 3, 6, 9]);
+
+        // a reused token starts over
+        storage.erase(&ids[4]);
+        assert_eq!(storage.discriminant(&ids[4]), None);
+        let reused = storage.place(5u32);
+        assert_eq!(reused.token_index(), ids[4].token_index());
+        assert_eq!(storage.discriminant(&reused), Some(0));
+
+        storage.clear();
+        let id = storage.place_with_discriminant(0u8, 7);
+        assert_eq!(storage.discriminant(&id), Some(7));
+
+        // tokens created outside of place keep theirs
+        let mut dest = Storage::new().with_discriminants();
+        dest.place(0u16);
+        let remap = storage.donate_bucket::<u8>(&mut dest);
+        assert_eq!(dest.discriminant(&remap.get(id).unwrap()), Some(7));
+    }
+
+    #[test]
+    fn len_counts_live_values() {
+        use super::*;
+
+        let mut storage = Storage::new().with_token_capacity(64);
+        storage.reserve::<u32>(32);
+        assert_eq!(storage.len(), 0);
+        assert!(storage.is_empty());
+
+        let ids = (0..8)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        storage.erase(&ids[0]);
+        storage.remove::<u32>(&ids[1]);
+        assert_eq!(storage.len(), 7);
+        assert_eq!(
+            storage.len(),
+            storage.iter::<u32>().count() + storage.iter::<u8>().count()
+        );
+        assert_eq!(storage.dangling_token_count(), 2);
+
+        storage.clear();
+        assert!(storage.is_empty());
+    }
+
+    #[test]
+    fn clear_trivial_and_dropping_types() {
+        use super::*;
+        use std::rc::Rc;
+
+        let mut storage = Storage::new();
+        let counted = Rc::new(());
+        for value in 0..1000 {
+            storage.place(value as u32);
+            storage.place(counted.clone());
+        }
+        assert_eq!(Rc::strong_count(&counted), 1001);
+
+        storage.clear();
+        assert_eq!(Rc::strong_count(&counted), 1);
+        assert_eq!(storage.iter::<u32>().count(), 0);
+        assert!(storage.is_empty());
+
+        let id = storage.place(7u32);
+        assert_eq!(*storage.get::<u32>(&id), 7);
+    }
+
+    #[test]
+    fn count() {
+        use super::*;
 
         let mut storage = Storage::new();
-        let red = storage.place::<Color>((String::from("red"), 255, 0, 0));
-        let green = storage.place::<Color>((String::from("green"), 0, 255, 0));
+        assert_eq!(storage.count::<u32>(), 0);
 
-        let mut ids = vec![];
-        for _ in 0..10_000 {
-            ids.push(storage.place::<Color>((String::from("blue"), 255, 0, 0)));
+        let ids = (0..10)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        storage.place(0u8);
+        storage.erase(&ids[2]);
+        assert_eq!(storage.count::<u32>(), 9);
+        assert_eq!(storage.count::<u8>(), 1);
+        assert_eq!(storage.count::<u64>(), 0);
+        assert_eq!(storage.count::<u32>(), storage.iter::<u32>().count());
+    }
+
+    #[test]
+    fn erase_trivial_and_dropping_types() {
+        use super::*;
+        use std::rc::Rc;
+
+        let mut storage = Storage::new();
+        let counted = Rc::new(());
+        let plain = (0..10)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        let dropping = (0..10)
+            .map(|_| storage.place(counted.clone()))
+            .collect::<Vec<_>>();
+
+        for index in [0, 9, 4] {
+            storage.erase(&plain[index]);
+            storage.erase(&dropping[index]);
         }
+        assert_eq!(Rc::strong_count(&counted), 8);
 
-        for id in ids.iter() {
+        let mut values = storage.iter::<u32>().copied().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![1, 2, 3, 5, 6, 7, 8]);
+        for (value, id) in plain.iter().enumerate() {
+            match [0, 9, 4].contains(&value) {
+                true => assert!(!storage.contains(id)),
+                false => assert_eq!(*storage.get::<u32>(id), value as u32),
+            }
+        }
+        storage.validate();
+    }
+
+    #[test]
+    fn reserve() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.reserve::<u64>(1000);
+        assert_eq!(storage.capacity::<u64>(), 1000);
+        assert!(storage.token_capacity() >= 1000);
+        assert!(storage.is_empty());
+
+        // the whole burst fits without growing the bucket or the tokens
+        let token_capacity = storage.token_capacity();
+        let ids = storage.place_n_with(1000, |value| value as u64);
+        assert_eq!(storage.capacity::<u64>(), 1000);
+        assert_eq!(storage.token_capacity(), token_capacity);
+
+        storage.reserve::<u64>(10);
+        assert!(storage.capacity::<u64>() >= 1010);
+        assert_eq!(*storage.get::<u64>(&ids[999]), 999);
+    }
+
+    #[test]
+    fn resize_to() {
+        use super::*;
+
+        let mut storage = Storage::new().with_discriminants();
+        let numbers = (0..100)
+            .map(|value| storage.place(value as u32))
+            .collect::<Vec<_>>();
+        let texts = (0..10)
+            .map(|value| storage.place_with_discriminant(value.to_string(), value))
+            .collect::<Vec<_>>();
+        for id in numbers.iter().step_by(3) {
             storage.erase(id);
         }
+        storage.ensure_bucket::<u8>();
 
-        assert!(storage.contains(&red));
-        assert!(storage.contains(&green));
+        let (mut resized, remap) = storage.resize_to::<crate::params::USize>();
+        assert_eq!(remap.len(), 76);
+        assert_eq!(resized.len(), 76);
+        resized.validate();
 
-        storage.erase(&red);
-        assert!(!storage.contains(&red));
-        assert!(storage.contains(&green));
+        for (value, id) in numbers.iter().enumerate() {
+            match remap.get(*id) {
+                Some(id) => assert_eq!(*resized.get::<u32>(&id), value as u32),
+                None => assert_eq!(value % 3, 0),
+            }
+        }
+        for (value, id) in texts.iter().enumerate() {
+            let id = remap.get(*id).unwrap();
+            assert_eq!(resized.get::<String>(&id), &value.to_string());
+            assert_eq!(resized.discriminant(&id), Some(value as u16));
+        }
 
-        storage.erase(&green);
-        assert!(!storage.contains(&red));
-        assert!(!storage.contains(&green));
+        let id = resized.place(1u8);
+        assert_eq!(*resized.get::<u8>(&id), 1);
+    }
+
+    #[test]
+    fn with_capacity_for() {
+        use super::*;
+
+        let mut storage = Storage::new()
+            .with_capacity_for::<u64>(1000)
+            .with_capacity_for::<u8>(10);
+        assert_eq!(storage.capacity::<u64>(), 1000);
+        assert_eq!(storage.capacity::<u8>(), 10);
+        assert_eq!(storage.capacity::<u16>(), 0);
+        assert!(storage.token_capacity() >= 1010);
+
+        storage.place_n_with(1000, |value| value as u64);
+        assert_eq!(storage.capacity::<u64>(), 1000);
+    }
+
+    #[test]
+    fn pack_tokens() {
+        use super::*;
+
+        let mut storage = Storage::new().with_discriminants();
+        let mut ids = (0..100)
+            .map(|value| storage.place_with_discriminant(value as u32, value % 7))
+            .collect::<Vec<_>>();
+        let text = storage.place("text".to_string());
+        for id in ids
+            .iter()
+            .filter(|id| usize::from(id.token_index()) % 4 != 0)
+        {
+            storage.erase(id);
+        }
+        assert_eq!(storage.dangling_token_count(), 75);
+        storage.set_eviction_cap::<u32>(25);
+
+        let remap = storage.pack_tokens();
+        assert_eq!(remap.len(), 26);
+        assert_eq!(storage.tokens.len(), 26);
+        assert_eq!(storage.dangling_token_count(), 0);
+        storage.validate();
+
+        let text = remap.get(text).unwrap();
+        assert_eq!(storage.get::<String>(&text), "text");
+        remap.apply_to(&mut ids);
+        for (value, id) in ids.iter().enumerate() {
+            match value % 4 {
+                0 => {
+                    assert_eq!(*storage.get::<u32>(id), value as u32);
+                    assert_eq!(storage.discriminant(id), Some(value as u16 % 7));
+                }
+                _ => assert_eq!(*id, Id::dangling()),
+            }
+        }
+
+        // the eviction order follows the remap
+        assert_eq!(storage.place_with_eviction(100u32).1, Some(0));
+
+        let id = storage.place(0u8);
+        assert_eq!(usize::from(id.token_index()), 26);
+    }
+
+    #[test]
+    fn shrink_to_fit_keeps_live_values() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..100u64).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let gone = storage.place(String::from("gone"));
+        storage.erase(&gone);
+        for id in ids.iter().skip(10) {
+            storage.remove::<u64>(id);
+        }
+        assert!(storage.capacity::<u64>() >= 100);
+
+        storage.shrink_to_fit();
+        assert_eq!(storage.capacity::<u64>(), 10);
+        assert_eq!(storage.capacity::<String>(), 0);
+        for (value, id) in ids.iter().take(10).enumerate() {
+            assert_eq!(*storage.get::<u64>(id), value as u64);
+        }
+
+        storage.place(10u64);
+        assert_eq!(storage.count::<u64>(), 11);
+        storage.validate();
+    }
+
+    #[test]
+    fn removal_batch() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..6u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let other = storage.place(0u8);
+        {
+            let mut batch = storage.removal_batch();
+            // the last value is queued after a value it would be swapped into
+            batch.queue(&ids[1]);
+            batch.queue(&ids[5]);
+            batch.queue(&ids[3]);
+            batch.queue(&ids[3]);
+            batch.queue(&other);
+            assert_eq!(batch.len(), 5);
+        }
+
+        assert_eq!(storage.len(), 3);
+        for (value, id) in ids.iter().enumerate() {
+            let removed = [1, 3, 5].contains(&value);
+            assert_eq!(storage.contains(id), !removed);
+            if !removed {
+                assert_eq!(*storage.get::<u32>(id), value as u32);
+            }
+        }
+        assert!(!storage.contains(&other));
+        storage.validate();
+
+        // stale ids are skipped
+        storage.removal_batch().queue(&ids[1]);
+        assert_eq!(storage.len(), 3);
+    }
+
+    #[test]
+    fn with_context() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..3u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        storage.place(0u8);
+
+        let sum = storage.with_context::<u32, _>(&ids[1], |value, neighbors| {
+            assert_eq!(neighbors.id(), ids[1]);
+            assert_eq!(neighbors.previous(), Some((ids[0], &0)));
+            assert_eq!(neighbors.next(), Some((ids[2], &2)));
+            neighbors.previous().unwrap().1 + value + neighbors.next().unwrap().1
+        });
+        assert_eq!(sum, Some(3));
+
+        storage.with_context::<u32, _>(&ids[0], |_, neighbors| {
+            assert!(neighbors.previous().is_none());
+        });
+        storage.with_context::<u32, _>(&ids[2], |_, neighbors| {
+            assert!(neighbors.next().is_none());
+        });
+
+        assert_eq!(storage.with_context::<u8, _>(&ids[1], |_, _| ()), None);
+        storage.erase(&ids[1]);
+        assert_eq!(storage.with_context::<u32, _>(&ids[1], |_, _| ()), None);
+    }
+
+    #[test]
+    fn cell_overhead() {
+        use super::*;
+        use crate::params::USize;
+
+        assert_eq!(Storage::<U32Size>::cell_overhead::<u32>(), (4, 4));
+        assert_eq!(Storage::<U32Size>::cell_overhead::<u64>(), (8, 8));
+        // 4 bytes of token index and 3 of padding
+        assert_eq!(Storage::<U32Size>::cell_overhead::<u8>(), (1, 7));
+        assert_eq!(Storage::<U32Size>::cell_overhead::<[u8; 4]>(), (4, 4));
+        assert_eq!(
+            Storage::<USize>::cell_overhead::<u64>(),
+            (8, size_of::<usize>())
+        );
+        assert_eq!(
+            Storage::<USize>::cell_overhead::<u8>(),
+            (1, 2 * size_of::<usize>() - 1)
+        );
+
+        const OVERHEAD: (usize, usize) = Storage::<U32Size>::cell_overhead::<u16>();
+        assert_eq!(OVERHEAD, (2, 6));
+    }
+
+    #[test]
+    fn try_place() {
+        use super::*;
+
+        #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+        struct TinySize(usize);
+        impl From<usize> for TinySize {
+            fn from(value: usize) -> Self {
+                Self(value)
+            }
+        }
+        impl From<TinySize> for usize {
+            fn from(value: TinySize) -> Self {
+                value.0
+            }
+        }
+        impl Size for TinySize {
+            fn max() -> usize {
+                4
+            }
+            fn bytes() -> usize {
+                1
+            }
+        }
+
+        let mut storage = Storage::new_with_tag_and_size::<TinySize, Unique32>();
+        let first = storage.try_place(0u8).unwrap();
+        storage.try_place(1u8).unwrap();
+        storage.place(0u16);
+        storage.place(1u16);
+
+        let error = storage.try_place(String::from("rejected")).unwrap_err();
+        assert_eq!(error.to_string(), "no token index left");
+        assert_eq!(error.into_inner(), "rejected");
+        assert_eq!(storage.len(), 4);
+        assert_eq!(
+            storage.try_place(2u8),
+            Err(PlaceError::TokensExhausted(2u8))
+        );
+
+        storage.erase(&first);
+        let id = storage.try_place(2u8).unwrap();
+        assert_eq!(*storage.get::<u8>(&id), 2);
+        storage.validate();
+    }
+
+    #[test]
+    fn place_typed_mut() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.place(0u32);
+        let (tid, value) = storage.place_typed_mut(vec![1u32]);
+        value.push(2);
+
+        storage.place(vec![0u32]);
+        assert_eq!(storage.get_typed(&tid), &[1, 2]);
+        assert_eq!(storage.count::<Vec<u32>>(), 2);
+    }
+
+    #[test]
+    fn get_panic_messages() {
+        use super::*;
+
+        fn message(f: impl FnOnce()) -> String {
+            let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_err();
+            payload.downcast_ref::<String>().unwrap().clone()
+        }
+
+        let mut storage = Storage::new();
+        let id = storage.place(0u32);
+        let removed = storage.place(1u32);
+        storage.erase(&removed);
+
+        assert_eq!(
+            message(|| {
+                storage.get::<u64>(&id);
+            }),
+            "type mismatch (expected u64, found u32)"
+        );
+        assert_eq!(
+            message(|| {
+                storage.get_mut::<u8>(&id);
+            }),
+            "type mismatch (expected u8, found u32)"
+        );
+        assert_eq!(
+            message(|| {
+                storage.get::<u32>(&removed);
+            }),
+            "value at token index 1 was removed"
+        );
+        assert_eq!(
+            message(|| {
+                storage.get_mut::<u32>(&removed);
+            }),
+            "value at token index 1 was removed"
+        );
+
+        let unknown = Id::<U32Size, Unique32>::new(100.into(), Unique32::default());
+        assert_eq!(
+            message(|| {
+                storage.get::<u32>(&unknown);
+            }),
+            "unknown token index 100"
+        );
+    }
+
+    #[test]
+    fn malformed_ids_are_rejected() {
+        use super::*;
+
+        // ids from untrusted sources go through the try_ accessors, never get
+        let mut storage = Storage::new();
+        let id = storage.place(7u32);
+        let removed = storage.place(8u32);
+        storage.erase(&removed);
+
+        let (token_index, tag) = id.into_raw();
+        let malformed = [
+            Id::from_raw(u32::MAX as usize, tag),
+            Id::from_raw(token_index + 1, tag),
+            Id::from_raw(token_index, tag + 1),
+            Id::from_raw(token_index, tag | 1 << 31),
+            Id::from_raw(token_index, 1 << 31),
+            Id::from_raw(removed.into_raw().0, removed.into_raw().1 | 1 << 31),
+            removed,
+            Id::dangling(),
+        ];
+        for bad in malformed.iter() {
+            assert!(!storage.contains(bad));
+            assert!(storage.try_get::<u32>(bad).is_none());
+            assert!(storage.try_get_mut::<u32>(bad).is_none());
+            assert!(storage.get_type_id(bad).is_none());
+            assert!(storage.try_remove::<u32>(bad).is_err());
+            // erasing removed again is the double removal track-removals reports
+            if *bad != removed {
+                storage.erase(bad);
+            }
+        }
+        assert!(Id::<U32Size, Unique32>::try_from_raw(usize::MAX, 0).is_none());
+
+        assert_eq!(storage.len(), 1);
+        assert_eq!(storage.try_get::<u32>(&id), Some(&7));
+        assert!(storage.try_get::<u64>(&id).is_none());
+        storage.validate();
+
+        // USize holds no more than u32::MAX either
+        let mut storage = Storage::new_with_tag_and_size::<crate::USize, Unique32>();
+        storage.place(7u32);
+        assert!(Id::<crate::USize, Unique32>::try_from_raw(1 << 33, 0).is_none());
+        assert!(Id::<crate::USize, Unique32>::try_from_raw(usize::MAX, 0).is_none());
+        let bad = Id::<crate::USize, Unique32>::try_from_raw(u32::MAX as usize, 0).unwrap();
+        assert!(!storage.contains(&bad));
+        assert!(storage.try_get::<u32>(&bad).is_none());
+        assert!(storage.try_remove::<u32>(&bad).is_err());
+    }
+
+    #[test]
+    fn try_remove() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(1u32);
+
+        assert_eq!(
+            storage.try_remove::<u64>(&id),
+            Err(RemoveError::TypeMismatch {
+                expected: "u64",
+                found: "u32"
+            })
+        );
+        assert!(storage.contains(&id));
+        assert_eq!(storage.try_remove::<u32>(&id), Ok(1));
+
+        let id = storage.place(2u32);
+        assert!(storage.remove::<u64>(&id).is_none());
+        assert_eq!(storage.remove::<u32>(&id), Some(2));
+        assert_eq!(
+            storage.try_remove::<u32>(&Id::dangling()),
+            Err(RemoveError::Stale)
+        );
+    }
+
+    #[test]
+    fn try_remove_stale() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let id = storage.place(1u32);
+        storage.erase(&id);
+        assert_eq!(storage.try_remove::<u32>(&id), Err(RemoveError::Stale));
+        assert_eq!(
+            RemoveError::Stale.to_string(),
+            "the value was already removed"
+        );
+    }
+
+    #[test]
+    fn get_disjoint_mut() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let a = storage.place(1u32);
+        let b = storage.place(2u32);
+        let text = storage.place(String::from("x"));
+        let float = storage.place(0.5f64);
+        let removed = storage.place(3u32);
+        storage.erase(&removed);
+
+        let [a_mut, b_mut, text_mut, float_mut, removed_mut] =
+            storage.get_disjoint_mut([&a, &b, &text, &float, &removed]);
+        let a_mut = a_mut.unwrap().downcast_mut::<u32>().unwrap();
+        let b_mut = b_mut.unwrap().downcast_mut::<u32>().unwrap();
+        std::mem::swap(a_mut, b_mut);
+        text_mut
+            .unwrap()
+            .downcast_mut::<String>()
+            .unwrap()
+            .push('y');
+        *float_mut.unwrap().downcast_mut::<f64>().unwrap() *= 4.0;
+        assert!(removed_mut.is_none());
+
+        assert_eq!(*storage.get::<u32>(&a), 2);
+        assert_eq!(*storage.get::<u32>(&b), 1);
+        assert_eq!(storage.get::<String>(&text), "xy");
+        assert_eq!(*storage.get::<f64>(&float), 2.0);
+
+        let aliased = std::panic::AssertUnwindSafe(|| {
+            storage.get_disjoint_mut([&a, &text, &a]);
+        });
+        assert!(std::panic::catch_unwind(aliased).is_err());
+    }
+
+    #[test]
+    fn get2_mut() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let count = storage.place(1u32);
+        let name = storage.place(String::from("a"));
+        let other = storage.place(2u32);
+
+        let (count_mut, name_mut) = storage.get2_mut::<u32, String>(&count, &name);
+        *count_mut += 1;
+        name_mut.push('b');
+        assert_eq!(*storage.get::<u32>(&count), 2);
+        assert_eq!(storage.get::<String>(&name), "ab");
+
+        assert!(storage.try_get2_mut::<u32, u32>(&count, &other).is_none());
+        assert!(storage.try_get2_mut::<u64, String>(&count, &name).is_none());
+        storage.erase(&name);
+        assert!(storage.try_get2_mut::<u32, String>(&count, &name).is_none());
+
+        let same_type = std::panic::AssertUnwindSafe(|| {
+            storage.get2_mut::<u32, u32>(&count, &other);
+        });
+        assert!(std::panic::catch_unwind(same_type).is_err());
+    }
+
+    #[test]
+    fn typed_get_mut() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let tid = storage.place_typed(vec![1u8]);
+        storage.get_typed_mut(&tid).push(2);
+        assert_eq!(storage.get_typed(&tid), &[1, 2]);
+
+        // the erased id reaches the same value
+        let id = Id::from(tid);
+        assert_eq!(storage.get::<Vec<u8>>(&id), &[1, 2]);
+
+        storage.erase(&id);
+        assert!(storage.try_get_typed(&tid).is_none());
+    }
+
+    #[test]
+    fn pack_tokens_rejects_old_ids() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..10u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        for id in ids.iter().skip(1).step_by(2) {
+            storage.erase(id);
+        }
+
+        let remap = storage.pack_tokens();
+        for id in ids.iter() {
+            assert!(!storage.contains(id));
+            assert!(storage.try_get::<u32>(id).is_none());
+        }
+        for value in 10..20u32 {
+            storage.place(value);
+        }
+        for id in ids.iter() {
+            assert!(!storage.contains(id));
+        }
+
+        for (value, id) in ids.iter().enumerate().step_by(2) {
+            let id = remap.get(*id).unwrap();
+            assert_eq!(*storage.get::<u32>(&id), value as u32);
+        }
+        storage.validate();
+    }
+
+    #[test]
+    fn resize_to_any_size() {
+        use super::*;
+
+        #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+        struct U16Size(u16);
+        impl From<usize> for U16Size {
+            fn from(value: usize) -> Self {
+                Self(u16::try_from(value).unwrap())
+            }
+        }
+        impl From<U16Size> for usize {
+            fn from(value: U16Size) -> Self {
+                value.0 as _
+            }
+        }
+        impl Size for U16Size {
+            fn max() -> usize {
+                u16::MAX as _
+            }
+            fn bytes() -> usize {
+                2
+            }
+        }
+
+        let mut storage = Storage::new();
+        let bytes = (0..50u8).map(|v| storage.place(v)).collect::<Vec<_>>();
+        let texts = (0..5)
+            .map(|v| storage.place(v.to_string()))
+            .collect::<Vec<_>>();
+        storage.erase(&bytes[10]);
+
+        let (mut resized, remap) = storage.resize_to::<U16Size>();
+        assert_eq!(resized.len(), 54);
+        assert_eq!(Storage::<U16Size>::cell_overhead::<u8>(), (1, 3));
+        for (value, id) in bytes.iter().enumerate().filter(|(value, _)| *value != 10) {
+            assert_eq!(*resized.get::<u8>(&remap.get(*id).unwrap()), value as u8);
+        }
+        for (value, id) in texts.iter().enumerate() {
+            let id = remap.get(*id).unwrap();
+            assert_eq!(resized.remove::<String>(&id), Some(value.to_string()));
+        }
+        resized.place(0u8);
+        resized.validate();
+    }
+
+    #[test]
+    fn exchange_keeps_eviction_order() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        storage.set_eviction_cap::<u32>(2);
+        let (a, _) = storage.place_with_eviction(1u32);
+        storage.place_with_eviction(2u32);
+        let (a, _) = storage.exchange(&a, 3u32).unwrap();
+
+        assert_eq!(storage.place_with_eviction(4u32).1, Some(2));
+        assert_eq!(storage.place_with_eviction(5u32).1, Some(3));
+        assert!(!storage.contains(&a));
+        assert_eq!(storage.count::<u32>(), 2);
+    }
+
+    #[test]
+    fn reset_keeps_tokens() {
+        use super::*;
+
+        let mut storage = Storage::new();
+        let ids = (0..4u32).map(|v| storage.place(v)).collect::<Vec<_>>();
+        storage.remove::<u32>(&ids[1]);
+        storage.reset();
+
+        assert!(ids.iter().all(|id| !storage.contains(id)));
+        assert_eq!(storage.len(), 0);
+        assert_eq!(storage.dangling_token_count(), 4);
+        storage.validate();
+
+        let id = storage.place(7u32);
+        assert_eq!(id.into_raw(), (0, 1));
+        assert_eq!(storage.dangling_token_count(), 3);
+        storage.validate();
+    }
+
+    #[test]
+    fn donate_bucket_queues_for_eviction() {
+        use super::*;
+
+        // whole allocation, dest has no bucket of u32 yet
+        let mut src = Storage::new();
+        let old = (0..3u32).map(|v| src.place(v)).collect::<Vec<_>>();
+        let mut dest = Storage::new();
+        dest.set_eviction_cap::<u32>(3);
+        let remap = src.donate_bucket::<u32>(&mut dest);
+        assert_eq!(dest.place_with_eviction(3u32).1, Some(0));
+        assert!(!dest.contains(&remap.get(old[0]).unwrap()));
+
+        // element by element
+        let mut src = Storage::new();
+        let old = src.place(4u32);
+        let mut dest = Storage::new();
+        dest.place(5u32);
+        dest.set_eviction_cap::<u32>(2);
+        let remap = src.donate_bucket::<u32>(&mut dest);
+        assert_eq!(dest.place_with_eviction(6u32).1, Some(5));
+        assert_eq!(dest.place_with_eviction(7u32).1, Some(4));
+        assert!(!dest.contains(&remap.get(old).unwrap()));
+    }
+
+    #[cfg(feature = "dirty-tracking")]
+    #[test]
+    fn donate_bucket_keeps_dirty_ids() {
+        use super::*;
+
+        for dest_has_bucket in [false, true] {
+            let mut src = Storage::new();
+            let ids = (0..4u32).map(|v| src.place(v)).collect::<Vec<_>>();
+            *src.get_mut::<u32>(&ids[2]) += 1;
+
+            let mut dest = Storage::new();
+            if dest_has_bucket {
+                dest.place(0u32);
+            }
+            let remap = src.donate_bucket::<u32>(&mut dest);
+
+            assert!(src.take_dirty::<u32>().is_empty());
+            assert_eq!(dest.take_dirty::<u32>(), vec![remap.get(ids[2]).unwrap()]);
+        }
     }
 }
@@ -1,4 +1,5 @@
-use crate::params::*;
+use crate::{error::TryReserveError, params::*};
+use core::alloc::Layout;
 
 #[derive(Copy, Clone)]
 pub(crate) struct Location<S: Size> {
@@ -27,9 +28,9 @@ pub(crate) struct Token<S: Size, U: UniqueTag> {
 }
 
 impl<S: Size, U: UniqueTag> Token<S, U> {
-    fn new(bucket_index: S, inbucket_index: S) -> Self {
+    fn new(tag: U, bucket_index: S, inbucket_index: S) -> Self {
         Self {
-            tag: U::default(),
+            tag,
             data: TokenData {
                 location: Location {
                     bucket_index,
@@ -43,16 +44,26 @@ impl<S: Size, U: UniqueTag> Token<S, U> {
         self.tag
     }
 
-    pub unsafe fn location(&self) -> &Location<S> {
-        debug_assert!(!self.tag.is_removed());
-        debug_assert!(!self.tag.is_locked());
-        &self.data.location
+    fn locked() -> Self {
+        let mut tag = U::default();
+        tag.mark_locked();
+        Self::new(tag, 0.into(), 0.into())
+    }
+
+    pub fn try_location(&self) -> Option<&Location<S>> {
+        if self.tag.is_removed() || self.tag.is_locked() {
+            return None;
+        }
+
+        Some(unsafe { &self.data.location })
     }
 }
 
 pub(crate) struct TokenBucket<S: Size, U: UniqueTag> {
     tokens: Vec<Token<S, U>>,
     free_cursor: Option<S>,
+    // tag of tokens pushed past the end, above every tag dropped by pack
+    floor: U,
 }
 
 impl<S: Size, U: UniqueTag> TokenBucket<S, U> {
@@ -60,6 +71,7 @@ impl<S: Size, U: UniqueTag> TokenBucket<S, U> {
         Self {
             tokens: vec![],
             free_cursor: None,
+            floor: U::default(),
         }
     }
 
@@ -89,7 +101,8 @@ impl<S: Size, U: UniqueTag> TokenBucket<S, U> {
         let token_index = self.tokens.len();
 
         assert_ne!(self.tokens.len(), S::max());
-        self.tokens.push(Token::new(bucket_index, inbucket_index));
+        self.tokens
+            .push(Token::new(self.floor, bucket_index, inbucket_index));
 
         (token_index.into(), self.tokens.last().unwrap().tag)
     }
@@ -119,6 +132,87 @@ impl<S: Size, U: UniqueTag> TokenBucket<S, U> {
         self.free_cursor = Some(token_index);
     }
 
+    // advances the generation of a live token in place, a token that can not advance
+    // gets locked and the location moves to a new token
+    pub fn reissue(&mut self, token_index: S) -> (S, U) {
+        let usize_token_index = token_index.into();
+        debug_assert!(usize_token_index < self.tokens.len());
+
+        let token = &mut self.tokens[usize_token_index];
+        let location = *token.try_location().unwrap();
+        let tag = token.tag.next();
+        if tag != token.tag {
+            token.tag = tag;
+            return (token_index, tag);
+        }
+
+        token.tag.mark_locked();
+        self.create(location.bucket_index, location.inbucket_index)
+    }
+
+    // gives a token for every location, in order, and drops the free list. A new tag
+    // is past every tag its index had before, so ids from before never resolve again,
+    // indexes whose tags can not advance stay locked
+    pub fn pack(&mut self, locations: &[(S, S)]) -> Vec<(S, U)> {
+        let old = core::mem::take(&mut self.tokens);
+        self.free_cursor = None;
+        let floor = self.floor;
+        let advanced = |token_index: usize| match old.get(token_index) {
+            Some(token) if token.tag.is_locked() => None,
+            Some(token) => {
+                let mut tag = token.tag;
+                tag.set_removed(false);
+                let next = tag.next();
+                (next != tag).then_some(next)
+            }
+            None => Some(floor),
+        };
+
+        let mut packed = Vec::with_capacity(locations.len());
+        self.tokens.reserve_exact(locations.len());
+        for &(bucket_index, inbucket_index) in locations {
+            loop {
+                let token_index = self.tokens.len();
+                assert_ne!(token_index, S::max());
+                match advanced(token_index) {
+                    Some(tag) => {
+                        self.tokens
+                            .push(Token::new(tag, bucket_index, inbucket_index));
+                        packed.push((token_index.into(), tag));
+                        break;
+                    }
+                    None => self.tokens.push(Token::locked()),
+                }
+            }
+        }
+
+        // the dropped tail is remembered as a floor for tokens pushed later, unless
+        // some of it is locked, then it is kept as free and locked tokens
+        let tail = self.tokens.len()..old.len();
+        if tail
+            .clone()
+            .all(|token_index| advanced(token_index).is_some())
+        {
+            for tag in tail.filter_map(advanced) {
+                if tag.current() > self.floor.current() {
+                    self.floor = tag;
+                }
+            }
+        } else {
+            for token_index in tail {
+                match advanced(token_index) {
+                    Some(tag) => {
+                        self.tokens.push(Token::new(tag, 0.into(), 0.into()));
+                        self.mark_removed(token_index.into());
+                    }
+                    None => self.tokens.push(Token::locked()),
+                }
+            }
+        }
+
+        packed
+    }
+
     pub fn set_inbucket_index(&mut self, token_index: S, inbucket_index: S) {
         let usize_token_index = token_index.into();
         debug_assert!(usize_token_index < self.tokens.len());
@@ -133,6 +227,24 @@ impl<S: Size, U: UniqueTag> TokenBucket<S, U> {
         Some(&self.tokens[usize_token_index])
     }
 
+    pub unsafe fn get_token_unchecked(&self, token_index: S) -> &Token<S, U> {
+        let usize_token_index = token_index.into();
+        debug_assert!(usize_token_index < self.tokens.len());
+        unsafe { self.tokens.get_unchecked(usize_token_index) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.tokens.capacity()
+    }
+
+    pub fn tags(&self) -> impl Iterator<Item = U> + '_ {
+        self.tokens.iter().map(|token| token.tag)
+    }
+
     pub fn contains(&self, token_index: S, tag: U) -> bool {
         match self.try_get_token(token_index) {
             Some(token) => tag == token.tag && !token.tag.is_removed() && !token.tag.is_locked(),
@@ -140,21 +252,58 @@ impl<S: Size, U: UniqueTag> TokenBucket<S, U> {
         }
     }
 
-    pub fn reset_tokens(&mut self) {
-        for token in self.tokens.iter_mut() {
-            let removed = token.tag.is_removed();
-            token.tag = U::default();
-            token.tag.set_removed(removed);
-        }
-    }
-
     pub fn clear(&mut self) {
         self.tokens.clear();
+        self.free_cursor = None;
+    }
+
+    // keeps every token, but frees it with its tag started over, locked ones included
+    pub fn reset_tokens(&mut self) {
+        self.free_cursor = None;
+        self.floor = U::default();
+        for token_index in (0..self.tokens.len()).rev() {
+            self.tokens[token_index].tag = U::default();
+            self.mark_removed(token_index.into());
+        }
     }
 
     pub fn shrink_to_fit(&mut self) {
         self.tokens.shrink_to_fit();
     }
+
+    // create would have to push past S::max(), locked tokens never become free
+    pub fn is_full(&self) -> bool {
+        self.free_cursor.is_none() && self.tokens.len() == S::max()
+    }
+
+    // free tokens are not counted, so this may underestimate
+    pub fn spare_capacity(&self) -> usize {
+        self.tokens.capacity() - self.tokens.len()
+    }
+
+    pub fn allocated_bytes(&self) -> usize {
+        self.tokens.capacity() * core::mem::size_of::<Token<S, U>>()
+    }
+
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.tokens.try_reserve(additional).map_err(|_| {
+            match self.tokens.len().checked_add(additional) {
+                Some(required) => match Layout::array::<Token<S, U>>(required) {
+                    Ok(layout) => TryReserveError::AllocError { layout },
+                    Err(_) => TryReserveError::CapacityOverflow,
+                },
+                None => TryReserveError::CapacityOverflow,
+            }
+        })
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.tokens.reserve(additional);
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.tokens.reserve_exact(additional);
+    }
 }
 
 impl<S: Size, U: UniqueTag> Default for TokenBucket<S, U> {
@@ -162,6 +311,124 @@ impl<S: Size, U: UniqueTag> Default for TokenBucket<S, U> {
         Self {
             tokens: vec![],
             free_cursor: None,
+            floor: U::default(),
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn reissue() {
+        use super::*;
+
+        let mut tokens = TokenBucket::<U32Size, Unique32>::new();
+        tokens.create(0.into(), 0.into());
+        tokens.create(0.into(), 1.into());
+
+        let (token_index, tag) = tokens.reissue(0.into());
+        assert_eq!(token_index, 0.into());
+        assert_eq!(tag.current(), 1);
+
+        // a saturated tag gets locked and the location moves to a new token
+        tokens.tokens[1].tag = Unique32::from_raw(tag.last() as _);
+        let (token_index, tag) = tokens.reissue(1.into());
+        assert_eq!(token_index, 2.into());
+        assert_eq!(tag, Unique32::default());
+        assert!(tokens.tokens[1].tag.is_locked());
+
+        let location = tokens.tokens[2].try_location().unwrap();
+        assert_eq!(location.bucket_index(), 0.into());
+        assert_eq!(location.inbucket_index(), 1.into());
+    }
+
+    #[test]
+    fn is_full() {
+        use super::*;
+
+        #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+        struct TinySize(usize);
+        impl From<usize> for TinySize {
+            fn from(value: usize) -> Self {
+                Self(value)
+            }
+        }
+        impl From<TinySize> for usize {
+            fn from(value: TinySize) -> Self {
+                value.0
+            }
+        }
+        impl Size for TinySize {
+            fn max() -> usize {
+                2
+            }
+            fn bytes() -> usize {
+                1
+            }
+        }
+
+        let mut tokens = TokenBucket::<TinySize, Unique32>::new();
+        tokens.create(0.into(), 0.into());
+        tokens.create(0.into(), 1.into());
+        assert!(tokens.is_full());
+
+        tokens.mark_removed(1.into());
+        assert!(!tokens.is_full());
+        assert_eq!(tokens.create(0.into(), 1.into()).0, 1.into());
+        assert!(tokens.is_full());
+
+        // a saturated tag locks its token on removal instead of freeing it
+        tokens.tokens[0].tag = Unique32::from_raw(Unique32::default().last() as _);
+        tokens.mark_removed(0.into());
+        assert!(tokens.tokens[0].tag.is_locked());
+        assert!(tokens.is_full());
+    }
+
+    #[test]
+    fn pack_skips_locked_indexes() {
+        use super::*;
+
+        let mut tokens = TokenBucket::<U32Size, Unique32>::new();
+        for inbucket_index in 0..4 {
+            tokens.create(0.into(), inbucket_index.into());
+        }
+        tokens.tokens[0].tag = Unique32::from_raw(Unique32::default().last() as _);
+        tokens.mark_removed(0.into());
+        tokens.tokens[3].tag = Unique32::from_raw(Unique32::default().last() as _);
+        tokens.mark_removed(3.into());
+        tokens.mark_removed(2.into());
+
+        // 0 is locked, so the live value moves to 1, 2 is dropped, 3 stays locked
+        let packed = tokens.pack(&[(0.into(), 0.into())]);
+        assert_eq!(packed, vec![(1.into(), Unique32::from_raw(1))]);
+        assert_eq!(tokens.len(), 4);
+        assert!(tokens.tokens[0].tag.is_locked());
+        assert!(tokens.tokens[3].tag.is_locked());
+        assert!(!tokens.contains(2.into(), Unique32::from_raw(1)));
+
+        let (token_index, tag) = tokens.create(0.into(), 1.into());
+        assert_eq!(token_index, 2.into());
+        assert_eq!(tag.current(), 3);
+    }
+
+    #[test]
+    fn reset_tokens() {
+        use super::*;
+
+        let mut tokens = TokenBucket::<U32Size, Unique32>::new();
+        for inbucket_index in 0..3 {
+            tokens.create(0.into(), inbucket_index.into());
+        }
+        tokens.tokens[1].tag = Unique32::from_raw(Unique32::default().last() as _);
+        tokens.mark_removed(1.into());
+        assert!(tokens.tokens[1].tag.is_locked());
+
+        tokens.reset_tokens();
+        assert_eq!(tokens.len(), 3);
+        for token_index in 0..3 {
+            let (created, tag) = tokens.create(0.into(), 0.into());
+            assert_eq!(created, token_index.into());
+            assert_eq!(tag, Unique32::from_raw(1));
         }
+        assert_eq!(tokens.len(), 3);
     }
 }
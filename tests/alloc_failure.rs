@@ -0,0 +1,50 @@
+use nitro::{Storage, TryReserveError};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// fails every allocation while FAIL is set, std collections abort on that,
+// so only fallible storage paths may run while it is set
+struct FailingAllocator;
+
+static FAIL: AtomicBool = AtomicBool::new(false);
+
+unsafe impl GlobalAlloc for FailingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match FAIL.load(Ordering::SeqCst) {
+            true => std::ptr::null_mut(),
+            false => unsafe { System.alloc(layout) },
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: FailingAllocator = FailingAllocator;
+
+#[test]
+fn failed_allocation_is_reported() {
+    let mut storage = Storage::new();
+    let ids = (0..100u64).map(|v| storage.place(v)).collect::<Vec<_>>();
+    for id in ids.iter().skip(10) {
+        storage.remove::<u64>(id);
+    }
+    let capacity = storage.capacity::<u64>();
+
+    FAIL.store(true, Ordering::SeqCst);
+    let reserved = storage.try_reserve::<u64>(1000);
+    FAIL.store(false, Ordering::SeqCst);
+
+    assert!(matches!(reserved, Err(TryReserveError::AllocError { .. })));
+    assert_eq!(storage.capacity::<u64>(), capacity);
+    for (value, id) in ids.iter().enumerate().take(10) {
+        assert_eq!(*storage.get::<u64>(id), value as u64);
+    }
+
+    storage.place(100u64);
+    assert_eq!(storage.len(), 11);
+}
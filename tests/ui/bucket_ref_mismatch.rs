@@ -0,0 +1,10 @@
+use nitro::*;
+
+struct A(u32);
+struct B(u64);
+
+fn main() {
+    let mut storage = Storage::new();
+    let mut bucket_ref = storage.bucket_ref::<A>();
+    bucket_ref.place(B(0));
+}
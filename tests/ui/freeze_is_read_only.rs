@@ -0,0 +1,9 @@
+use nitro::*;
+
+fn main() {
+    let mut storage = Storage::new();
+    let id = storage.place(0u32);
+
+    let frozen = storage.freeze();
+    *frozen.get_mut::<u32>(&id) += 1;
+}